@@ -0,0 +1,126 @@
+use super::{image, util};
+
+/*
+Settings of adaptive sampling: every pixel takes a minimum number of samples, then keeps taking more
+until the standard error of its mean luminance falls below `threshold` times that mean, up to the
+number of samples per pixel of the sampler. Pixels that converge quickly, such as the background,
+leave the samples to the noisy ones.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AdaptiveSampling {
+    min_samples: u32,
+    threshold: f32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, threshold: f32) -> AdaptiveSampling {
+        AdaptiveSampling {
+            // At least two samples are needed to estimate a variance
+            min_samples: min_samples.max(2),
+            threshold,
+        }
+    }
+
+    pub fn get_min_samples(&self) -> u32 {
+        self.min_samples
+    }
+
+    pub fn is_converged(&self, statistics: &PixelStatistics) -> bool {
+        statistics.count >= self.min_samples && statistics.get_relative_error() <= self.threshold
+    }
+}
+
+// Running mean and variance of the luminance of the samples of a pixel, with Welford's algorithm
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PixelStatistics {
+    count: u32,
+    mean: f32,
+    squared_deviations: f32,
+}
+
+impl PixelStatistics {
+    pub fn add(&mut self, color: util::Color) {
+        let luminance = color.get_luminance();
+        self.count += 1;
+        let deviation = luminance - self.mean;
+        self.mean += deviation / self.count as f32;
+        self.squared_deviations += deviation * (luminance - self.mean);
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.count
+    }
+
+    // Exact representation of the statistics, to save them with the state of a render
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        bytes[0..4].copy_from_slice(&self.count.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.mean.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.squared_deviations.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 12]) -> PixelStatistics {
+        let word = |k: usize| [bytes[k], bytes[k + 1], bytes[k + 2], bytes[k + 3]];
+        PixelStatistics {
+            count: u32::from_le_bytes(word(0)),
+            mean: f32::from_le_bytes(word(4)),
+            squared_deviations: f32::from_le_bytes(word(8)),
+        }
+    }
+
+    // Standard error of the mean luminance relative to it, infinite until there are two samples
+    pub fn get_relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let variance = self.squared_deviations / (self.count - 1) as f32;
+        let standard_error = (variance / self.count as f32).sqrt();
+        // The floor keeps nearly black pixels from asking for an impossible precision
+        standard_error / self.mean.max(1e-3)
+    }
+}
+
+/*
+Image of the number of samples taken by every pixel, for a row-major list of counts, going from
+black through red and yellow to white at `max_samples`.
+*/
+pub fn get_heatmap(counts: &[u32], height: usize, width: usize, max_samples: u32) -> image::Image {
+    let mut heatmap = image::Image::new(height, width);
+    for i in 0..height {
+        for j in 0..width {
+            let x = 3.0 * counts[i * width + j] as f32 / max_samples.max(1) as f32;
+            let color = util::Color::new(
+                x.min(1.0),
+                (x - 1.0).clamp(0.0, 1.0),
+                (x - 2.0).clamp(0.0, 1.0),
+            );
+            heatmap.set_pixel(i, j, color);
+        }
+    }
+    heatmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_pixels_converge_at_the_minimum_test() {
+        let adaptive = AdaptiveSampling::new(4, 0.01);
+        let mut constant = PixelStatistics::default();
+        let mut noisy = PixelStatistics::default();
+        for k in 0..4 {
+            constant.add(util::Color::new(0.2, 0.2, 0.2));
+            assert!(!adaptive.is_converged(&noisy));
+            let value = if k % 2 == 0 { 0.0 } else { 1.0 };
+            noisy.add(util::Color::new(value, value, value));
+        }
+        assert!(adaptive.is_converged(&constant));
+        assert!(!adaptive.is_converged(&noisy));
+        // Mean 0.5 and variance 1/3, for a standard error of 0.289
+        assert!((noisy.mean - 0.5).abs() < 1e-6);
+        assert!(AdaptiveSampling::new(4, 0.6).is_converged(&noisy));
+        assert!(!AdaptiveSampling::new(4, 0.5).is_converged(&noisy));
+    }
+}
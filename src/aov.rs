@@ -0,0 +1,243 @@
+use super::{exr, image, scene, util};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+/*
+Arbitrary output variable: a buffer rendered along with the image, for compositing. The light
+passes add up to the image, before its display transform, and are filtered the same way. The
+geometric passes are averaged over the pixel, and are zero where the rays hit no object. IDs can't
+be averaged, so they keep the value of the first sample of each pixel.
+*/
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Aov {
+    // Distance from the camera along the ray
+    Depth,
+    Position,
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    // Light sources and background seen directly
+    Emission,
+    DirectDiffuse,
+    DirectSpecular,
+    // Light bouncing off other surfaces first, which stays black as long as the scene only computes
+    // direct lighting; compositing setups built on the full set of light passes still get them
+    IndirectDiffuse,
+    IndirectSpecular,
+    // Light removed by shadows, which gives the unshadowed image once added to the diffuse and
+    // specular passes
+    Shadow,
+}
+
+impl Aov {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Emission => "emission",
+            Aov::DirectDiffuse => "direct_diffuse",
+            Aov::DirectSpecular => "direct_specular",
+            Aov::IndirectDiffuse => "indirect_diffuse",
+            Aov::IndirectSpecular => "indirect_specular",
+            Aov::Shadow => "shadow",
+        }
+    }
+
+    pub fn is_light(&self) -> bool {
+        matches!(
+            self,
+            Aov::Emission
+                | Aov::DirectDiffuse
+                | Aov::DirectSpecular
+                | Aov::IndirectDiffuse
+                | Aov::IndirectSpecular
+                | Aov::Shadow
+        )
+    }
+
+    pub fn is_id(&self) -> bool {
+        *self == Aov::ObjectId || *self == Aov::MaterialId
+    }
+
+    // Value of the buffer for a sample, in its first channels
+    pub fn get_value(&self, sample: &scene::RadianceSample) -> util::Color {
+        let black = util::Color::new(0.0, 0.0, 0.0);
+        let scalar = |value: f32| util::Color::new(value, value, value);
+        match (self, &sample.hit) {
+            (Aov::Emission, _) => sample.emitted,
+            (Aov::DirectDiffuse, _) => sample.direct_diffuse,
+            (Aov::DirectSpecular, _) => sample.direct_specular,
+            (Aov::IndirectDiffuse, _) | (Aov::IndirectSpecular, _) => black,
+            (Aov::Shadow, _) => sample.shadow,
+            (_, None) => black,
+            (Aov::Depth, Some(hit)) => scalar(hit.distance),
+            (Aov::Position, Some(hit)) => util::Color::from(hit.position),
+            (Aov::Normal, Some(hit)) => util::Color::from(hit.normal),
+            (Aov::Albedo, Some(hit)) => hit.albedo,
+            (Aov::ObjectId, Some(hit)) => scalar(hit.object_id as f32),
+            (Aov::MaterialId, Some(hit)) => scalar(hit.material_id as f32),
+        }
+    }
+
+    fn get_channel_names(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Position | Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    // Half floats lose the precision of positions and distances, and can't count IDs past 2048
+    fn get_pixel_type(&self) -> exr::PixelType {
+        match self {
+            Aov::Depth | Aov::Position | Aov::ObjectId | Aov::MaterialId => exr::PixelType::Float,
+            _ => exr::PixelType::Half,
+        }
+    }
+}
+
+// Writes the image and its buffers as the layers of a single OpenEXR file, named after the buffers
+pub fn write_exr(
+    filepath: &str,
+    image: &image::Image,
+    aovs: &[(Aov, image::Image)],
+) -> io::Result<()> {
+    let mut layers = vec![exr::Layer::new("", image)];
+    for (aov, aov_image) in aovs {
+        layers.push(
+            exr::Layer::new(aov.get_name(), aov_image)
+                .with_channel_names(aov.get_channel_names())
+                .with_pixel_type(aov.get_pixel_type()),
+        );
+    }
+    let mut buffer = BufWriter::new(File::create(filepath)?);
+    exr::write(&mut buffer, &layers, exr::Compression::Zip)?;
+    buffer.flush()
+}
+
+/*
+Writes the image to the file and every buffer to a file of its own, named by inserting the name of
+the buffer before the extension, as in `render.depth.exr`, in the format given by the extension.
+*/
+pub fn write_separately(
+    filepath: &str,
+    image: &image::Image,
+    aovs: &[(Aov, image::Image)],
+) -> io::Result<()> {
+    image.write(filepath)?;
+    for (aov, aov_image) in aovs {
+        aov_image.write(&get_filepath(filepath, *aov))?;
+    }
+    Ok(())
+}
+
+fn get_filepath(filepath: &str, aov: Aov) -> String {
+    let path = Path::new(filepath);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => path
+            .with_extension(format!("{}.{}", aov.get_name(), extension))
+            .to_string_lossy()
+            .into_owned(),
+        None => format!("{}.{}", filepath, aov.get_name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math;
+
+    #[test]
+    fn aov_values_and_filepaths_test() {
+        let mut sample = scene::RadianceSample::new(util::Color::new(0.5, 0.5, 0.5));
+        assert_eq!(
+            Aov::Depth.get_value(&sample),
+            util::Color::new(0.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            Aov::Emission.get_value(&sample),
+            util::Color::new(0.5, 0.5, 0.5)
+        );
+        assert!(Aov::IndirectDiffuse.is_light());
+        assert_eq!(Aov::IndirectSpecular.get_name(), "indirect_specular");
+        assert_eq!(
+            Aov::IndirectDiffuse.get_value(&sample),
+            util::Color::new(0.0, 0.0, 0.0)
+        );
+        sample.hit = Some(scene::Hit {
+            distance: 2.0,
+            position: math::Vector::new(1.0, 2.0, 3.0),
+            normal: math::Vector::new(0.0, 1.0, 0.0),
+            albedo: util::Color::new(0.2, 0.3, 0.4),
+            object_id: 3,
+            material_id: 1,
+        });
+        assert_eq!(
+            Aov::Depth.get_value(&sample),
+            util::Color::new(2.0, 2.0, 2.0)
+        );
+        assert_eq!(
+            Aov::Position.get_value(&sample),
+            util::Color::new(1.0, 2.0, 3.0)
+        );
+        assert_eq!(Aov::ObjectId.get_value(&sample).r, 3.0);
+        assert_eq!(
+            get_filepath("out/render.exr", Aov::Normal),
+            "out/render.normal.exr"
+        );
+        assert_eq!(get_filepath("render", Aov::Depth), "render.depth");
+    }
+
+    fn get_test_images() -> (image::Image, Vec<(Aov, image::Image)>) {
+        let mut image = image::Image::new(2, 3);
+        let mut depth = image::Image::new(2, 3);
+        for i in 0..2 {
+            for j in 0..3 {
+                let value = (i * 3 + j) as f32;
+                image.set_pixel(i, j, util::Color::new(value * 0.1, 0.5, 1.0));
+                depth.set_pixel(i, j, util::Color::new(value, value, value));
+            }
+        }
+        (image, vec![(Aov::Depth, depth)])
+    }
+
+    #[test]
+    fn write_exr_test() {
+        let (image, aovs) = get_test_images();
+        let filepath = std::env::temp_dir().join("lightpaths_aov_test.exr");
+        let filepath = filepath.to_str().unwrap();
+        write_exr(filepath, &image, &aovs).unwrap();
+        let bytes = std::fs::read(filepath).unwrap();
+        std::fs::remove_file(filepath).unwrap();
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        // Channels are named after their layer, and depths are stored as full floats
+        let channel = b"depth.Z\0\x02\0\0\0";
+        assert!(bytes.windows(channel.len()).any(|window| window == channel));
+        let channel = b"R\0\x01\0\0\0";
+        assert!(bytes.windows(channel.len()).any(|window| window == channel));
+    }
+
+    #[test]
+    fn write_separately_round_trip_test() {
+        let (image, aovs) = get_test_images();
+        let filepath = std::env::temp_dir().join("lightpaths_aov_test.pfm");
+        let filepath = filepath.to_str().unwrap();
+        write_separately(filepath, &image, &aovs).unwrap();
+        let read = image::Image::read(filepath).unwrap();
+        let depth_filepath = get_filepath(filepath, Aov::Depth);
+        let read_depth = image::Image::read(&depth_filepath).unwrap();
+        std::fs::remove_file(filepath).unwrap();
+        std::fs::remove_file(&depth_filepath).unwrap();
+        assert_eq!(read.get_data(), image.get_data());
+        assert_eq!(read_depth.get_data(), aovs[0].1.get_data());
+    }
+}
@@ -1,7 +1,30 @@
-use super::{math, util};
+use super::{image, lens, math, sampling, util};
+use std::f32::consts::PI;
+
+pub trait Camera {
+    /*
+    Ray through the point (i, j) of the image, in pixels, where `lens_sample` is a pair of uniform
+    random numbers in [0, 1) used by cameras with a lens. Returns None where the projection doesn't
+    cover the image, like outside the circle of a fisheye.
+    */
+    fn generate_ray(&self, i: f32, j: f32, lens_sample: (f32, f32)) -> Option<util::Ray>;
+    fn get_resolution(&self) -> Dimensions;
+    // Times at which the shutter opens and closes. Each ray sees the scene at a time in between.
+    fn get_shutter_interval(&self) -> (f32, f32);
+
+    // Factor applied to the radiance reaching the camera before it is written to the image
+    fn get_exposure_scale(&self) -> f32 {
+        1.0
+    }
+
+    // Fraction of the light kept at the point (i, j) of the image, darkening it towards the borders
+    fn get_vignetting(&self, _i: f32, _j: f32) -> f32 {
+        1.0
+    }
+}
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub struct Camera {
+pub struct PerspectiveCamera {
     position: math::Vector,
     focal_distance: f32,
     lens_dimensions: Dimensions,
@@ -10,45 +33,539 @@ pub struct Camera {
     u: math::Vector,
     v: math::Vector,
     w: math::Vector,
+    aperture_radius: f32,
+    focus_distance: f32,
+    aperture_blades: u32,
+    aperture_rotation: f32,
+    shutter_interval: (f32, f32),
+    exposure_scale: f32,
+    distortion: Option<lens::Distortion>,
+    vignetting: Option<lens::Vignetting>,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         position: math::Vector,
         direction: math::Vector,
         focal_distance: f32,
         lens_dimensions: Dimensions,
         screen_dimensions: Dimensions,
-    ) -> Camera {
+    ) -> PerspectiveCamera {
         let up_vector = math::Vector::new(0.0, 1.0, 0.0);
-        let w = -direction.normalize();
-        let u = math::Vector::cross_product(up_vector, w).normalize();
-        let v = math::Vector::cross_product(w, u);
+        PerspectiveCamera::from_frame(
+            position,
+            direction,
+            up_vector,
+            focal_distance,
+            lens_dimensions,
+            screen_dimensions,
+        )
+    }
+
+    /*
+    Camera placed at `eye` and looking at `target`, with `up` giving the vertical direction of the
+    image. The aspect ratio of the lens follows the resolution, so the field of view is only given
+    for one of the axes.
+    */
+    pub fn look_at(
+        eye: math::Vector,
+        target: math::Vector,
+        up: math::Vector,
+        field_of_view: FieldOfView,
+        resolution: Dimensions,
+    ) -> PerspectiveCamera {
+        let aspect_ratio = resolution.width / resolution.height;
+        let lens_dimensions = field_of_view.get_lens_dimensions(aspect_ratio);
+        PerspectiveCamera::from_frame(eye, target - eye, up, 1.0, lens_dimensions, resolution)
+    }
 
-        Camera {
+    // Pinhole camera with the lens at the focal distance along `direction`, and no other settings
+    fn from_frame(
+        position: math::Vector,
+        direction: math::Vector,
+        up_vector: math::Vector,
+        focal_distance: f32,
+        lens_dimensions: Dimensions,
+        screen_dimensions: Dimensions,
+    ) -> PerspectiveCamera {
+        let (u, v, w) = compute_basis(direction, up_vector);
+
+        PerspectiveCamera {
             position: position,
             focal_distance: focal_distance,
             lens_dimensions: lens_dimensions,
             screen_dimensions: screen_dimensions,
-            lens_top_left_corner: position + v * (lens_dimensions.height / 2.0)
+            lens_top_left_corner: v * (lens_dimensions.height / 2.0)
                 - u * (lens_dimensions.width / 2.0),
             u: u,
             v: v,
             w: w,
+            shutter_interval: (0.0, 0.0),
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            exposure_scale: 1.0,
+            distortion: None,
+            vignetting: None,
+        }
+    }
+
+    /*
+    Turns the pinhole into a thin lens of the given radius, so that only the objects at the focus
+    distance, measured along the viewing direction, are sharp.
+    */
+    pub fn with_depth_of_field(
+        mut self,
+        aperture_radius: f32,
+        focus_distance: f32,
+    ) -> PerspectiveCamera {
+        self.aperture_radius = aperture_radius;
+        self.focus_distance = focus_distance;
+        self
+    }
+
+    // Polygonal aperture, rotated in degrees, giving shaped bokeh. Zero blades is a round aperture.
+    pub fn with_aperture_shape(mut self, blades: u32, rotation: f32) -> PerspectiveCamera {
+        self.aperture_blades = if blades >= 3 { blades } else { 0 };
+        self.aperture_rotation = rotation.to_radians();
+        self
+    }
+
+    pub fn with_shutter(mut self, open: f32, close: f32) -> PerspectiveCamera {
+        self.shutter_interval = (open, close);
+        self
+    }
+
+    /*
+    Exposes the image like a physical camera with the given settings, for scenes lit in real-world
+    units. The shutter stays open for the shutter speed, starting at time zero. The f-number only
+    changes the brightness, leaving the depth of field as it is.
+    */
+    pub fn with_exposure(mut self, exposure: Exposure) -> PerspectiveCamera {
+        self.exposure_scale = exposure.get_scale();
+        self.shutter_interval = (0.0, exposure.shutter_speed);
+        self
+    }
+
+    /*
+    Exposes the image like `with_exposure`, and opens the aperture of a lens with the given focal
+    length, in millimeters, at the f-number of the exposure, focused at the focus distance. The scene
+    is measured in meters.
+    */
+    pub fn with_physical_lens(
+        self,
+        exposure: Exposure,
+        focal_length: f32,
+        focus_distance: f32,
+    ) -> PerspectiveCamera {
+        self.with_exposure(exposure)
+            .with_depth_of_field(exposure.get_aperture_radius(focal_length), focus_distance)
+    }
+
+    // Renders the image as seen through a real lens, to match footage shot with it
+    pub fn with_distortion(mut self, distortion: lens::Distortion) -> PerspectiveCamera {
+        self.distortion = Some(distortion);
+        self
+    }
+
+    pub fn with_vignetting(mut self, vignetting: lens::Vignetting) -> PerspectiveCamera {
+        self.vignetting = Some(vignetting);
+        self
+    }
+
+    /*
+    ST map undistorting images rendered with the lens distortion of this camera. Each pixel of the
+    map holds, in its red and green channels, the horizontal and vertical coordinates of the point of
+    the distorted image to read, in [0, 1] inside the frame and with the vertical one starting at the
+    bottom.
+    */
+    pub fn get_undistortion_st_map(&self) -> image::Image {
+        let height = self.screen_dimensions.height as usize;
+        let width = self.screen_dimensions.width as usize;
+        let mut st_map = image::Image::new(height, width);
+        for i in 0..height {
+            for j in 0..width {
+                let (x, y) = self.get_normalized_coordinates(i as f32, j as f32);
+                let (x, y) = match self.distortion {
+                    Some(distortion) => distortion.distort(x, y),
+                    None => (x, y),
+                };
+                let s = x * self.focal_distance / self.lens_dimensions.width + 0.5;
+                let t = y * self.focal_distance / self.lens_dimensions.height + 0.5;
+                st_map.set_pixel(i, j, util::Color::new(s, t, 0.0));
+            }
+        }
+        st_map
+    }
+
+    // Coordinates of the point (i, j) of the image on the lens, relative to its center, in focal lengths
+    fn get_normalized_coordinates(&self, i: f32, j: f32) -> (f32, f32) {
+        (
+            ((j + 0.5) / self.screen_dimensions.width - 0.5) * self.lens_dimensions.width
+                / self.focal_distance,
+            (0.5 - (i + 0.5) / self.screen_dimensions.height) * self.lens_dimensions.height
+                / self.focal_distance,
+        )
+    }
+
+    // Normalized coordinates of the ideal pinhole image seen at the point (i, j) of the image
+    fn get_undistorted_coordinates(&self, i: f32, j: f32) -> (f32, f32) {
+        let (x, y) = self.get_normalized_coordinates(i, j);
+        match self.distortion {
+            Some(distortion) => distortion.undistort(x, y),
+            None => (x, y),
+        }
+    }
+
+    /*
+    Copy of the camera moved sideways by `offset`, towards the right of the image when positive, as
+    seen by one eye of a stereo pair. With a convergence distance, the lens is shifted so that both
+    eyes frame the same area at that distance, which then appears at the depth of the screen.
+    */
+    pub fn get_eye(&self, offset: f32, convergence_distance: Option<f32>) -> PerspectiveCamera {
+        let mut eye = *self;
+        eye.position = self.position + self.u * offset;
+        if let Some(convergence_distance) = convergence_distance {
+            let lens_shift = -offset * self.focal_distance / convergence_distance;
+            eye.lens_top_left_corner = self.lens_top_left_corner + self.u * lens_shift;
+        }
+        eye
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn generate_ray(&self, i: f32, j: f32, lens_sample: (f32, f32)) -> Option<util::Ray> {
+        // The center of the lens is only away from the axis when the lens is shifted
+        let lens_center = self.lens_top_left_corner - self.v * (self.lens_dimensions.height / 2.0)
+            + self.u * (self.lens_dimensions.width / 2.0);
+        let (x, y) = self.get_undistorted_coordinates(i, j);
+        let ray_direction = lens_center + (self.u * x + self.v * y - self.w) * self.focal_distance;
+
+        if self.aperture_radius <= 0.0 {
+            return Some(util::Ray::new(self.position, ray_direction));
+        }
+        let (x, y) = if self.aperture_blades >= 3 {
+            sampling::uniform_sample_polygon(
+                lens_sample,
+                self.aperture_blades,
+                self.aperture_rotation + PI / 2.0,
+            )
+        } else {
+            sampling::concentric_sample_disk(lens_sample)
+        };
+        let lens_point = self.position
+            + self.u * (x * self.aperture_radius)
+            + self.v * (y * self.aperture_radius);
+        // The rays through every point of the lens meet where the pinhole ray hits the focal plane
+        let focus_point = self.position
+            + ray_direction
+                * (self.focus_distance / -math::Vector::dot_product(ray_direction, self.w));
+
+        Some(util::Ray::new(lens_point, focus_point - lens_point))
+    }
+
+    fn get_resolution(&self) -> Dimensions {
+        self.screen_dimensions
+    }
+
+    fn get_shutter_interval(&self) -> (f32, f32) {
+        self.shutter_interval
+    }
+
+    fn get_exposure_scale(&self) -> f32 {
+        self.exposure_scale
+    }
+
+    fn get_vignetting(&self, i: f32, j: f32) -> f32 {
+        match self.vignetting {
+            Some(vignetting) => {
+                let (x, y) = self.get_undistorted_coordinates(i, j);
+                vignetting.get_falloff(x, y)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+// Exposure settings of a physical camera
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Exposure {
+    pub iso: f32,
+    pub shutter_speed: f32, // In seconds
+    pub f_number: f32,
+}
+
+impl Exposure {
+    pub fn new(iso: f32, shutter_speed: f32, f_number: f32) -> Exposure {
+        Exposure {
+            iso,
+            shutter_speed,
+            f_number,
+        }
+    }
+
+    // Exposure value of the settings, relative to ISO 100
+    pub fn get_ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /*
+    Factor converting luminance, in cd/m², to the range of the image. It follows the saturation-based
+    sensitivity of a sensor, where the luminance saturating it is 1.2 * 2^EV100.
+    */
+    pub fn get_scale(&self) -> f32 {
+        1.0 / (1.2 * 2.0f32.powf(self.get_ev100()))
+    }
+
+    // Radius of the aperture, in meters, of a lens with the given focal length in millimeters
+    pub fn get_aperture_radius(&self, focal_length: f32) -> f32 {
+        focal_length / 1000.0 / (2.0 * self.f_number)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FieldOfView {
+    // Angle in degrees between the top and the bottom of the image
+    Vertical(f32),
+    // Angle in degrees between the left and the right of the image
+    Horizontal(f32),
+    // Focal length and width of the sensor of a physical camera, in the same unit, like millimeters
+    FocalLength {
+        focal_length: f32,
+        sensor_width: f32,
+    },
+}
+
+impl FieldOfView {
+    // Size of the lens placed at a focal distance of one
+    fn get_lens_dimensions(&self, aspect_ratio: f32) -> Dimensions {
+        match *self {
+            FieldOfView::Vertical(angle) => {
+                let height = 2.0 * (angle.to_radians() / 2.0).tan();
+                Dimensions::new(height, height * aspect_ratio)
+            }
+            FieldOfView::Horizontal(angle) => {
+                let width = 2.0 * (angle.to_radians() / 2.0).tan();
+                Dimensions::new(width / aspect_ratio, width)
+            }
+            FieldOfView::FocalLength {
+                focal_length,
+                sensor_width,
+            } => {
+                let width = sensor_width / focal_length;
+                Dimensions::new(width / aspect_ratio, width)
+            }
+        }
+    }
+}
+
+// Parallel projection, for technical views where sizes don't change with the distance
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct OrthographicCamera {
+    position: math::Vector,
+    view_dimensions: Dimensions,
+    screen_dimensions: Dimensions,
+    u: math::Vector,
+    v: math::Vector,
+    w: math::Vector,
+    shutter_interval: (f32, f32),
+}
+
+impl OrthographicCamera {
+    // `view_height` is the height of the area seen by the camera, in the units of the scene
+    pub fn look_at(
+        eye: math::Vector,
+        target: math::Vector,
+        up: math::Vector,
+        view_height: f32,
+        resolution: Dimensions,
+    ) -> OrthographicCamera {
+        let aspect_ratio = resolution.width / resolution.height;
+        let (u, v, w) = compute_basis(target - eye, up);
+
+        OrthographicCamera {
+            position: eye,
+            view_dimensions: Dimensions::new(view_height, view_height * aspect_ratio),
+            screen_dimensions: resolution,
+            u,
+            v,
+            w,
+            shutter_interval: (0.0, 0.0),
+        }
+    }
+
+    pub fn with_shutter(mut self, open: f32, close: f32) -> OrthographicCamera {
+        self.shutter_interval = (open, close);
+        self
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn generate_ray(&self, i: f32, j: f32, _lens_sample: (f32, f32)) -> Option<util::Ray> {
+        let x = ((j + 0.5) / self.screen_dimensions.width - 0.5) * self.view_dimensions.width;
+        let y = (0.5 - (i + 0.5) / self.screen_dimensions.height) * self.view_dimensions.height;
+        Some(util::Ray::new(
+            self.position + self.u * x + self.v * y,
+            -self.w,
+        ))
+    }
+
+    fn get_resolution(&self) -> Dimensions {
+        self.screen_dimensions
+    }
+
+    fn get_shutter_interval(&self) -> (f32, f32) {
+        self.shutter_interval
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FisheyeMapping {
+    // The distance from the center of the image grows linearly with the angle to the view direction
+    Equidistant,
+    // Every pixel covers the same solid angle
+    Equisolid,
+}
+
+// Circular fisheye, whose image circle fits the shorter side of the image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FisheyeCamera {
+    position: math::Vector,
+    mapping: FisheyeMapping,
+    field_of_view: f32,
+    screen_dimensions: Dimensions,
+    u: math::Vector,
+    v: math::Vector,
+    w: math::Vector,
+    shutter_interval: (f32, f32),
+}
+
+impl FisheyeCamera {
+    // `field_of_view` is the angle in degrees covered by the diameter of the image circle
+    pub fn look_at(
+        eye: math::Vector,
+        target: math::Vector,
+        up: math::Vector,
+        mapping: FisheyeMapping,
+        field_of_view: f32,
+        resolution: Dimensions,
+    ) -> FisheyeCamera {
+        let (u, v, w) = compute_basis(target - eye, up);
+
+        FisheyeCamera {
+            position: eye,
+            mapping,
+            field_of_view: field_of_view.to_radians(),
+            screen_dimensions: resolution,
+            u,
+            v,
+            w,
+            shutter_interval: (0.0, 0.0),
         }
     }
 
-    pub fn generate_ray(&self, i: f32, j: f32) -> util::Ray {
-        let ray_direction = self.lens_top_left_corner
-            - self.v
-                * self.lens_dimensions.height
-                * ((i as f32 + 0.5) / self.screen_dimensions.height)
-            + self.u
-                * self.lens_dimensions.width
-                * ((j as f32 + 0.5) / self.screen_dimensions.width)
-            - self.w * self.focal_distance;
+    pub fn with_shutter(mut self, open: f32, close: f32) -> FisheyeCamera {
+        self.shutter_interval = (open, close);
+        self
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn generate_ray(&self, i: f32, j: f32, _lens_sample: (f32, f32)) -> Option<util::Ray> {
+        let radius_in_pixels = self
+            .screen_dimensions
+            .height
+            .min(self.screen_dimensions.width)
+            / 2.0;
+        let x = (j + 0.5 - self.screen_dimensions.width / 2.0) / radius_in_pixels;
+        let y = (self.screen_dimensions.height / 2.0 - (i + 0.5)) / radius_in_pixels;
+        let radius = (x * x + y * y).sqrt();
+        if radius > 1.0 {
+            return None;
+        }
+        let half_field_of_view = self.field_of_view / 2.0;
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * half_field_of_view,
+            FisheyeMapping::Equisolid => {
+                2.0 * (radius * (half_field_of_view / 2.0).sin())
+                    .clamp(-1.0, 1.0)
+                    .asin()
+            }
+        };
+        let (sin_phi, cos_phi) = if radius > 0.0 {
+            (y / radius, x / radius)
+        } else {
+            (0.0, 0.0)
+        };
+        let direction = -self.w * theta.cos() + (self.u * cos_phi + self.v * sin_phi) * theta.sin();
+
+        Some(util::Ray::new(self.position, direction))
+    }
+
+    fn get_resolution(&self) -> Dimensions {
+        self.screen_dimensions
+    }
+
+    fn get_shutter_interval(&self) -> (f32, f32) {
+        self.shutter_interval
+    }
+}
+
+// 360° panorama, mapping longitude to the horizontal axis and latitude to the vertical axis
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EquirectangularCamera {
+    position: math::Vector,
+    screen_dimensions: Dimensions,
+    u: math::Vector,
+    v: math::Vector,
+    w: math::Vector,
+    shutter_interval: (f32, f32),
+}
+
+impl EquirectangularCamera {
+    // The center of the image looks towards `target`
+    pub fn look_at(
+        eye: math::Vector,
+        target: math::Vector,
+        up: math::Vector,
+        resolution: Dimensions,
+    ) -> EquirectangularCamera {
+        let (u, v, w) = compute_basis(target - eye, up);
+
+        EquirectangularCamera {
+            position: eye,
+            screen_dimensions: resolution,
+            u,
+            v,
+            w,
+            shutter_interval: (0.0, 0.0),
+        }
+    }
+
+    pub fn with_shutter(mut self, open: f32, close: f32) -> EquirectangularCamera {
+        self.shutter_interval = (open, close);
+        self
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn generate_ray(&self, i: f32, j: f32, _lens_sample: (f32, f32)) -> Option<util::Ray> {
+        let longitude = ((j + 0.5) / self.screen_dimensions.width - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - (i + 0.5) / self.screen_dimensions.height) * PI;
+        let direction = -self.w * (latitude.cos() * longitude.cos())
+            + self.u * (latitude.cos() * longitude.sin())
+            + self.v * latitude.sin();
+
+        Some(util::Ray::new(self.position, direction))
+    }
 
-        util::Ray::new(self.position, ray_direction)
+    fn get_resolution(&self) -> Dimensions {
+        self.screen_dimensions
+    }
+
+    fn get_shutter_interval(&self) -> (f32, f32) {
+        self.shutter_interval
     }
 }
 
@@ -61,12 +578,28 @@ pub struct Dimensions {
 impl Dimensions {
     pub fn new(height: f32, width: f32) -> Dimensions {
         Dimensions {
-            height: height as f32,
-            width: width as f32,
+            height: height,
+            width: width,
         }
     }
 }
 
+// Basis of a camera looking along `direction`. If it is parallel to `up_vector`, another up is chosen.
+fn compute_basis(
+    direction: math::Vector,
+    up_vector: math::Vector,
+) -> (math::Vector, math::Vector, math::Vector) {
+    let w = -direction.normalize();
+    let mut u = math::Vector::cross_product(up_vector, w);
+    if u.magnitude() < 1e-6 {
+        let (fallback_up, _) = w.orthonormal_basis();
+        u = math::Vector::cross_product(fallback_up, w);
+    }
+    let u = u.normalize();
+    let v = math::Vector::cross_product(w, u);
+    (u, v, w)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,7 +608,7 @@ mod tests {
     fn camera_coordinates_test() {
         let position = math::Vector::new(0.0, 0.0, 0.0);
         let direction = math::Vector::new(0.0, 0.0, 1.0);
-        let camera = Camera::new(
+        let camera = PerspectiveCamera::new(
             position,
             direction,
             1.0,
@@ -85,7 +618,7 @@ mod tests {
 
         assert_eq!(
             camera,
-            Camera {
+            PerspectiveCamera {
                 position: math::Vector::new(0.0, 0.0, 0.0),
                 focal_distance: 1.0,
                 lens_dimensions: Dimensions::new(2.0, 2.0),
@@ -93,8 +626,166 @@ mod tests {
                 lens_top_left_corner: math::Vector::new(1.0, 1.0, 0.0),
                 u: math::Vector::new(-1.0, 0.0, 0.0),
                 v: math::Vector::new(0.0, 1.0, 0.0),
-                w: math::Vector::new(0.0, 0.0, -1.0)
+                w: math::Vector::new(0.0, 0.0, -1.0),
+                aperture_radius: 0.0,
+                focus_distance: 1.0,
+                aperture_blades: 0,
+                aperture_rotation: 0.0,
+                shutter_interval: (0.0, 0.0),
+                exposure_scale: 1.0,
+                distortion: None,
+                vignetting: None,
             }
         );
     }
+
+    #[test]
+    fn camera_away_from_origin_test() {
+        let position = math::Vector::new(1.0, 2.0, -3.0);
+        let camera = PerspectiveCamera::new(
+            position,
+            math::Vector::new(0.0, 0.0, 1.0),
+            1.0,
+            Dimensions::new(2.0, 2.0),
+            Dimensions::new(20.0, 20.0),
+        );
+        // Rays leave from the camera, so the ones through the center point straight ahead
+        let ray = camera.generate_ray(9.5, 9.5, (0.5, 0.5)).unwrap();
+        assert_eq!(ray.origin, position);
+        assert_eq!(ray.direction, math::Vector::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn look_at_straight_down_test() {
+        let camera = PerspectiveCamera::look_at(
+            math::Vector::new(0.0, 5.0, 0.0),
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 1.0, 0.0),
+            FieldOfView::Vertical(90.0),
+            Dimensions::new(10.0, 20.0),
+        );
+        assert!(!camera.u.x.is_nan() && !camera.u.y.is_nan() && !camera.u.z.is_nan());
+        assert_eq!(camera.w, math::Vector::new(0.0, 1.0, 0.0));
+        let lens = camera.lens_dimensions;
+        assert!((lens.height - 2.0).abs() < 1e-5 && (lens.width - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rays_through_the_lens_converge_on_the_focal_plane_test() {
+        let camera = PerspectiveCamera::new(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            1.0,
+            Dimensions::new(2.0, 2.0),
+            Dimensions::new(20.0, 20.0),
+        )
+        .with_depth_of_field(0.1, 3.0)
+        .with_aperture_shape(6, 15.0);
+        for &lens_sample in [(0.1, 0.9), (0.7, 0.2)].iter() {
+            let ray = camera.generate_ray(4.0, 12.0, lens_sample).unwrap();
+            assert!(ray.origin.magnitude() > 0.0);
+            let t = (3.0 - ray.origin.z) / ray.direction.z;
+            let focus_point = ray.origin + ray.direction * t;
+            let pinhole_ray = camera.generate_ray(4.0, 12.0, (0.5, 0.5)).unwrap();
+            let expected = pinhole_ray.origin
+                + pinhole_ray.direction * ((3.0 - pinhole_ray.origin.z) / pinhole_ray.direction.z);
+            assert!((focus_point - expected).magnitude() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn fisheye_covers_only_the_image_circle_test() {
+        let camera = FisheyeCamera::look_at(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            math::Vector::new(0.0, 1.0, 0.0),
+            FisheyeMapping::Equisolid,
+            180.0,
+            Dimensions::new(10.0, 20.0),
+        );
+        let center = camera.generate_ray(4.5, 9.5, (0.5, 0.5)).unwrap();
+        assert!((center.direction - math::Vector::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        let edge = camera.generate_ray(4.5, 14.49, (0.5, 0.5)).unwrap();
+        assert!(edge.direction.z.abs() < 1e-2);
+        assert!(camera.generate_ray(0.0, 0.0, (0.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn equirectangular_wraps_around_the_viewer_test() {
+        let camera = EquirectangularCamera::look_at(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            math::Vector::new(0.0, 1.0, 0.0),
+            Dimensions::new(10.0, 20.0),
+        );
+        let front = camera.generate_ray(4.5, 9.5, (0.5, 0.5)).unwrap();
+        assert!((front.direction - math::Vector::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        let back = camera.generate_ray(4.5, -0.5, (0.5, 0.5)).unwrap();
+        assert!((back.direction - math::Vector::new(0.0, 0.0, -1.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn physical_exposure_test() {
+        let exposure = Exposure::new(100.0, 1.0 / 100.0, 16.0);
+        assert!((exposure.get_ev100() - 14.64386).abs() < 1e-4);
+        let camera = PerspectiveCamera::look_at(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            math::Vector::new(0.0, 1.0, 0.0),
+            FieldOfView::FocalLength {
+                focal_length: 50.0,
+                sensor_width: 36.0,
+            },
+            Dimensions::new(20.0, 30.0),
+        )
+        .with_exposure(exposure);
+        assert_eq!(camera.get_shutter_interval(), (0.0, 0.01));
+        assert_eq!(camera.aperture_radius, 0.0);
+        assert!((exposure.get_aperture_radius(50.0) - 0.0015625).abs() < 1e-7);
+        assert!((camera.get_exposure_scale() * 1.2 * 25600.0 - 1.0).abs() < 1e-4);
+
+        let camera = camera.with_physical_lens(exposure, 50.0, 3.0);
+        assert_eq!(camera.get_shutter_interval(), (0.0, 0.01));
+        assert!((camera.aperture_radius - 0.0015625).abs() < 1e-7);
+        assert_eq!(camera.focus_distance, 3.0);
+    }
+
+    #[test]
+    fn distorted_rays_follow_the_lens_model_test() {
+        let distortion = lens::Distortion::new(-0.2, 0.05, 0.0, 0.0, 0.0);
+        let pinhole = PerspectiveCamera::new(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            1.0,
+            Dimensions::new(2.0, 2.0),
+            Dimensions::new(20.0, 20.0),
+        );
+        let camera = pinhole.with_distortion(distortion);
+        let center = camera.generate_ray(9.5, 9.5, (0.5, 0.5)).unwrap();
+        assert!((center.direction - math::Vector::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        // With barrel distortion, the border of the image sees further out than the pinhole does
+        let border = camera
+            .generate_ray(9.5, 19.0, (0.5, 0.5))
+            .unwrap()
+            .direction;
+        let pinhole_border = pinhole
+            .generate_ray(9.5, 19.0, (0.5, 0.5))
+            .unwrap()
+            .direction;
+        assert!((border.x / border.z).abs() > (pinhole_border.x / pinhole_border.z).abs());
+        // Distorting the direction of the ray brings it back to the pixel it was traced for
+        let (distorted_x, _) = distortion.distort(border.x / border.z, border.y / border.z);
+        let (pinhole_x, _) = pinhole.get_normalized_coordinates(9.5, 19.0);
+        assert!((distorted_x.abs() - pinhole_x.abs()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn focal_length_field_of_view_test() {
+        let field_of_view = FieldOfView::FocalLength {
+            focal_length: 50.0,
+            sensor_width: 36.0,
+        };
+        let lens = field_of_view.get_lens_dimensions(1.5);
+        assert!((lens.width - 0.72).abs() < 1e-6 && (lens.height - 0.48).abs() < 1e-6);
+    }
 }
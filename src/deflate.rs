@@ -0,0 +1,223 @@
+/*
+DEFLATE compression (RFC 1951) wrapped in a zlib stream (RFC 1950), as stored by PNG and by the ZIP
+compression of OpenEXR. Repeated strings are found with hash chains over a 32 KiB window and encoded
+greedily in a single block of fixed Huffman codes, which gets most of the gain on rendered images for
+a fraction of the code needed by dynamic codes.
+*/
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Compresses `data` into a complete zlib stream
+pub fn compress_zlib(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window, and the check bits making the header a multiple of 31
+    let mut stream = vec![0x78, 0x9c];
+    stream.extend(deflate(data));
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+// Compresses `data` into a raw DEFLATE stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // Final block, compressed with the fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = find_longest_match(data, position, &head, &previous);
+        let step = if length >= MIN_MATCH {
+            write_match(&mut writer, length, distance);
+            length
+        } else {
+            write_literal(&mut writer, data[position] as u16);
+            1
+        };
+        for inserted in position..(position + step) {
+            if inserted + MIN_MATCH <= data.len() {
+                let hash = hash(&data[inserted..]);
+                previous[inserted % WINDOW_SIZE] = head[hash];
+                head[hash] = inserted;
+            }
+        }
+        position += step;
+    }
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // Summing up to 5552 bytes can't overflow before the modulo is taken
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+// Longest earlier string matching the one at `position`, as its length and distance
+fn find_longest_match(
+    data: &[u8],
+    position: usize,
+    head: &[usize],
+    previous: &[usize],
+) -> (usize, usize) {
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let mut best_length = 0;
+    let mut best_distance = 0;
+    let mut candidate = head[hash(&data[position..])];
+    let mut chain = 0;
+    while candidate != usize::MAX && chain < MAX_CHAIN {
+        let distance = position - candidate;
+        if distance > WINDOW_SIZE {
+            break;
+        }
+        let length = data[candidate..]
+            .iter()
+            .zip(data[position..(position + max_length)].iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = distance;
+            if length == max_length {
+                break;
+            }
+        }
+        let next = previous[candidate % WINDOW_SIZE];
+        // Older entries of the window have been overwritten by newer positions
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+        chain += 1;
+    }
+    (best_length, best_distance)
+}
+
+// Writes a literal byte, or the end of block for 256, with its fixed Huffman code
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.write_huffman_code(code, length);
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let length_code = LENGTH_BASES.partition_point(|&base| base as usize <= length) - 1;
+    write_literal(writer, 257 + length_code as u16);
+    writer.write_bits(
+        (length - LENGTH_BASES[length_code] as usize) as u32,
+        LENGTH_EXTRA_BITS[length_code] as u32,
+    );
+    let distance_code = DISTANCE_BASES.partition_point(|&base| base as usize <= distance) - 1;
+    writer.write_huffman_code(distance_code as u16, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASES[distance_code] as usize) as u32,
+        DISTANCE_EXTRA_BITS[distance_code] as u32,
+    );
+}
+
+// Packs bits starting from the least significant bit of each byte, as DEFLATE requires
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn write_huffman_code(&mut self, code: u16, length: u32) {
+        let reversed = code.reverse_bits() >> (16 - length);
+        self.write_bits(reversed as u32, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_test() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn zlib_stream_test() {
+        assert_eq!(
+            compress_zlib(b"a"),
+            [0x78, 0x9c, 0x4b, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]
+        );
+        // A literal followed by a match of length 9 at distance 1
+        assert_eq!(
+            compress_zlib(b"aaaaaaaaaa"),
+            [0x78, 0x9c, 0x4b, 0x84, 0x03, 0x00, 0x14, 0xe1, 0x03, 0xcb]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use super::{deflate, image};
+use std::io;
+use std::io::prelude::*;
+
+const MAGIC_NUMBER: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// Version 2 of the format, for a single part stored in scanlines
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PixelType {
+    Half,
+    Float,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Compression {
+    None,
+    // Deflate compression of each scanline
+    Zips,
+    // Deflate compression of blocks of 16 scanlines, smaller but slower to read back partially
+    Zip,
+}
+
+impl Compression {
+    fn get_code(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zips => 2,
+            Compression::Zip => 3,
+        }
+    }
+
+    fn get_lines_per_block(self) -> usize {
+        match self {
+            Compression::None | Compression::Zips => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+/*
+Image stored in an OpenEXR file, whose channels are named after the layer, as in `normal.R` or
+`depth.Z`. The channels of the main image are usually stored in a layer without name. By default
+the channels of the image are named R, G, B and A, but a layer can keep only the first channels
+of the image under other names, like a single Z channel for the depth.
+*/
+pub struct Layer<'a> {
+    name: String,
+    image: &'a image::Image,
+    channel_names: Vec<String>,
+    pixel_type: PixelType,
+}
+
+impl<'a> Layer<'a> {
+    pub fn new(name: &str, image: &'a image::Image) -> Layer<'a> {
+        Layer {
+            name: name.to_string(),
+            image,
+            channel_names: ["R", "G", "B", "A"][..image.get_channels()]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            pixel_type: PixelType::Half,
+        }
+    }
+
+    pub fn with_channel_names(mut self, names: &[&str]) -> Layer<'a> {
+        assert!(
+            !names.is_empty() && names.len() <= self.image.get_channels(),
+            "the layer must keep between one channel and every channel of the image"
+        );
+        self.channel_names = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    pub fn with_pixel_type(mut self, pixel_type: PixelType) -> Layer<'a> {
+        self.pixel_type = pixel_type;
+        self
+    }
+}
+
+// Channel of the file, which OpenEXR requires to be sorted by name
+struct Channel<'a> {
+    name: String,
+    image: &'a image::Image,
+    index: usize,
+    pixel_type: PixelType,
+}
+
+// Writes the layers, which must all have the same size, as a single scanline OpenEXR image
+pub fn write<W: Write>(
+    writer: &mut W,
+    layers: &[Layer],
+    compression: Compression,
+) -> io::Result<()> {
+    let first = layers
+        .first()
+        .ok_or_else(|| invalid_input("an OpenEXR file needs at least one layer"))?;
+    let height = first.image.get_height();
+    let width = first.image.get_width();
+    if height == 0 || width == 0 {
+        return Err(invalid_input("an OpenEXR image can't be empty"));
+    }
+
+    let mut channels = Vec::new();
+    for layer in layers {
+        if layer.image.get_height() != height || layer.image.get_width() != width {
+            return Err(invalid_input("every layer must have the same size"));
+        }
+        for (index, channel_name) in layer.channel_names.iter().enumerate() {
+            let name = if layer.name.is_empty() {
+                channel_name.clone()
+            } else {
+                format!("{}.{}", layer.name, channel_name)
+            };
+            channels.push(Channel {
+                name,
+                image: layer.image,
+                index,
+                pixel_type: layer.pixel_type,
+            });
+        }
+    }
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    if channels.windows(2).any(|pair| pair[0].name == pair[1].name) {
+        return Err(invalid_input("two channels have the same name"));
+    }
+
+    let header = write_header(&channels, height, width, compression);
+    let lines_per_block = compression.get_lines_per_block();
+    let mut blocks = Vec::new();
+    for first_line in (0..height).step_by(lines_per_block) {
+        let last_line = (first_line + lines_per_block).min(height);
+        let mut data = Vec::new();
+        for i in first_line..last_line {
+            for channel in channels.iter() {
+                let channels_per_pixel = channel.image.get_channels();
+                let row = &channel.image.get_data()[i * width * channels_per_pixel..];
+                for j in 0..width {
+                    let value = row[j * channels_per_pixel + channel.index];
+                    match channel.pixel_type {
+                        PixelType::Half => data.extend_from_slice(&to_half(value).to_le_bytes()),
+                        PixelType::Float => data.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+        }
+        if compression != Compression::None {
+            data = compress_zip(&data);
+        }
+        blocks.push((first_line, data));
+    }
+
+    writer.write_all(&MAGIC_NUMBER)?;
+    writer.write_all(&VERSION)?;
+    writer.write_all(&header)?;
+    // The offset table gives the position of every block from the start of the file
+    let mut offset = (MAGIC_NUMBER.len() + VERSION.len() + header.len() + 8 * blocks.len()) as u64;
+    for (_, data) in blocks.iter() {
+        writer.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+    for (first_line, data) in blocks.iter() {
+        writer.write_all(&(*first_line as i32).to_le_bytes())?;
+        writer.write_all(&(data.len() as i32).to_le_bytes())?;
+        writer.write_all(data)?;
+    }
+    Ok(())
+}
+
+fn write_header(
+    channels: &[Channel],
+    height: usize,
+    width: usize,
+    compression: Compression,
+) -> Vec<u8> {
+    let mut header = Vec::new();
+
+    let mut channel_list = Vec::new();
+    for channel in channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        let pixel_type: i32 = match channel.pixel_type {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        };
+        channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+        // Not perceptually linear, three reserved bytes, and one sample per pixel in x and y
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1_i32.to_le_bytes());
+        channel_list.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+
+    write_attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[compression.get_code()],
+    );
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1].iter() {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    // Scanlines stored from top to bottom
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    header.push(0);
+    header
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/*
+ZIP compression of a block: the bytes of the values are split in two halves, with the first and the
+second byte of every value, and replaced by their difference with the previous byte, which makes
+smooth images much more compressible. A block that doesn't shrink is stored as it is.
+*/
+fn compress_zip(data: &[u8]) -> Vec<u8> {
+    let mut reordered: Vec<u8> = data.iter().step_by(2).copied().collect();
+    reordered.extend(data.iter().skip(1).step_by(2));
+    let mut previous = reordered.first().copied().unwrap_or(0);
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    let compressed = deflate::compress_zlib(&reordered);
+    if compressed.len() < data.len() {
+        compressed
+    } else {
+        data.to_vec()
+    }
+}
+
+// Nearest half-precision float, rounding ties to even and overflowing to infinity
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 255 {
+        // Infinity, or NaN which must keep a non-zero mantissa
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 31 {
+        return sign | 0x7c00;
+    }
+    let (half, remainder, halfway) = if half_exponent <= 0 {
+        // Subnormal half, which keeps the implicit leading bit in its mantissa
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        (
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        )
+    } else {
+        (
+            ((half_exponent as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1fff,
+            0x1000,
+        )
+    };
+    // A carry out of the mantissa correctly moves on to the next exponent
+    let rounded = if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    fn half_conversion_test() {
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.1), 0x2e66);
+        assert_eq!(to_half(65504.0), 0x7bff);
+        assert_eq!(to_half(1e6), 0x7c00);
+        assert_eq!(to_half(2_f32.powi(-24)), 0x0001);
+        assert_eq!(to_half(2_f32.powi(-26)), 0);
+    }
+
+    #[test]
+    fn uncompressed_layers_test() {
+        let mut beauty = image::Image::new(1, 1);
+        beauty.set_pixel(0, 0, util::Color::new(0.5, 1.0, 2.0));
+        let mut depth = image::Image::new(1, 1);
+        depth.set_pixel(0, 0, util::Color::new(3.0, 3.0, 3.0));
+        let layers = [
+            Layer::new("", &beauty),
+            Layer::new("depth", &depth)
+                .with_channel_names(&["Z"])
+                .with_pixel_type(PixelType::Float),
+        ];
+        let mut bytes = Vec::new();
+        write(&mut bytes, &layers, Compression::None).unwrap();
+        assert_eq!(bytes[..4], MAGIC_NUMBER);
+        // The only block holds the channels B, G, R and depth.Z, sorted by name
+        let block = &bytes[bytes.len() - 18..];
+        assert_eq!(&block[..8], &[0, 0, 0, 0, 10, 0, 0, 0]);
+        assert_eq!(&block[8..10], &0x4000_u16.to_le_bytes());
+        assert_eq!(&block[10..12], &0x3c00_u16.to_le_bytes());
+        assert_eq!(&block[12..14], &0x3800_u16.to_le_bytes());
+        assert_eq!(&block[14..], &3_f32.to_le_bytes());
+    }
+}
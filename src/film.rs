@@ -0,0 +1,139 @@
+use super::{filter, image, util};
+use std::io;
+use std::io::prelude::*;
+
+/*
+Accumulates the samples of a render. Each sample is splatted to every pixel whose center is within
+the radius of the filter, weighted by the filter, and a pixel is the weighted average of the samples
+it received. Positions are in pixels, with the center of the pixel (i, j) at (i, j).
+*/
+pub struct Film {
+    height: usize,
+    width: usize,
+    filter: filter::Filter,
+    sums: Vec<util::Color>,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(height: usize, width: usize, filter: filter::Filter) -> Film {
+        Film {
+            height,
+            width,
+            filter,
+            sums: vec![util::Color::new(0.0, 0.0, 0.0); height * width],
+            weights: vec![0.0; height * width],
+        }
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn add_sample(&mut self, i: f32, j: f32, color: util::Color) {
+        let radius = self.filter.get_radius();
+        // Pixels with an offset from the sample in [-radius, radius), as the filters are half-open
+        let first_row = ((i - radius).floor() + 1.0).max(0.0) as usize;
+        let last_row = (i + radius).floor().min(self.height as f32 - 1.0);
+        let first_column = ((j - radius).floor() + 1.0).max(0.0) as usize;
+        let last_column = (j + radius).floor().min(self.width as f32 - 1.0);
+        if last_row < 0.0 || last_column < 0.0 {
+            return;
+        }
+        for row in first_row..=(last_row as usize) {
+            for column in first_column..=(last_column as usize) {
+                let weight = self.filter.evaluate(i - row as f32, j - column as f32);
+                if weight != 0.0 {
+                    let index = row * self.width + column;
+                    self.sums[index] += color * weight;
+                    self.weights[index] += weight;
+                }
+            }
+        }
+    }
+
+    pub fn get_image(&self) -> image::Image {
+        let mut image = image::Image::new(self.height, self.width);
+        for i in 0..self.height {
+            for j in 0..self.width {
+                let index = i * self.width + j;
+                // Negative lobes can cancel the weights out, leaving nothing meaningful to average
+                if self.weights[index] > 0.0 {
+                    image.set_pixel(i, j, self.sums[index] * (1.0 / self.weights[index]));
+                }
+            }
+        }
+        image
+    }
+
+    // Writes the sums and weights of every pixel, to be read back into a film of the same size
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+        writer.write_all(&(self.width as u32).to_le_bytes())?;
+        for (sum, weight) in self.sums.iter().zip(self.weights.iter()) {
+            for value in [sum.r, sum.g, sum.b, *weight].iter() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_state<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        let mut bytes = [0; 4];
+        let mut next = || -> io::Result<[u8; 4]> {
+            reader.read_exact(&mut bytes)?;
+            Ok(bytes)
+        };
+        let height = u32::from_le_bytes(next()?) as usize;
+        let width = u32::from_le_bytes(next()?) as usize;
+        if height != self.height || width != self.width {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the saved film is {}x{} instead of {}x{}",
+                    width, height, self.width, self.height
+                ),
+            ));
+        }
+        for (sum, weight) in self.sums.iter_mut().zip(self.weights.iter_mut()) {
+            sum.r = f32::from_le_bytes(next()?);
+            sum.g = f32::from_le_bytes(next()?);
+            sum.b = f32::from_le_bytes(next()?);
+            *weight = f32::from_le_bytes(next()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_filter_keeps_samples_in_their_pixel_test() {
+        let mut film = Film::new(2, 2, filter::Filter::Box { radius: 0.5 });
+        film.add_sample(-0.5, 0.2, util::Color::new(1.0, 0.0, 0.0));
+        film.add_sample(0.4999, 0.2, util::Color::new(0.0, 1.0, 0.0));
+        film.add_sample(0.5, 0.2, util::Color::new(0.0, 0.0, 1.0));
+        let image = film.get_image();
+        assert_eq!(image.get_pixel(0, 0), util::Color::new(0.5, 0.5, 0.0));
+        assert_eq!(image.get_pixel(1, 0), util::Color::new(0.0, 0.0, 1.0));
+        assert_eq!(image.get_pixel(0, 1), util::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn tent_filter_splats_to_neighbors_test() {
+        let mut film = Film::new(1, 3, filter::Filter::Tent { radius: 1.0 });
+        film.add_sample(0.0, 1.25, util::Color::new(1.0, 1.0, 1.0));
+        film.add_sample(0.0, 0.0, util::Color::new(0.0, 0.0, 0.0));
+        let image = film.get_image();
+        // The first pixel gets nothing from the first sample, the second gets 3/4 of it
+        assert_eq!(image.get_pixel(0, 0), util::Color::new(0.0, 0.0, 0.0));
+        assert_eq!(image.get_pixel(0, 1), util::Color::new(1.0, 1.0, 1.0));
+        assert_eq!(image.get_pixel(0, 2), util::Color::new(1.0, 1.0, 1.0));
+    }
+}
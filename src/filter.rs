@@ -0,0 +1,111 @@
+use std::f32::consts::PI;
+
+/*
+Pixel reconstruction filter, weighting the contribution of a sample to the pixels around it by its
+offset from their centers, in pixels. Every filter is separable and is zero beyond its radius.
+Mitchell-Netravali and Lanczos have negative lobes, which keep the image sharp but can ring around
+very bright edges.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, sigma: f32 },
+    // The default parameters recommended by Mitchell and Netravali are b = c = 1/3
+    Mitchell { radius: f32, b: f32, c: f32 },
+    // Sinc windowed by a sinc stretched over the radius, which gives the number of lobes
+    Lanczos { radius: f32 },
+}
+
+impl Filter {
+    pub fn get_radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let radius = self.get_radius();
+        // The support is half-open so that a sample on the border of a box belongs to a single pixel
+        if x < -radius || x >= radius {
+            return 0.0;
+        }
+        let x = x.abs();
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, sigma } => {
+                // Shifted down to reach zero at the radius instead of being cut abruptly
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { radius } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_support_test() {
+        let box_filter = Filter::Box { radius: 0.5 };
+        assert_eq!(box_filter.evaluate(-0.5, 0.2), 1.0);
+        assert_eq!(box_filter.evaluate(0.5, 0.2), 0.0);
+        let tent = Filter::Tent { radius: 1.0 };
+        assert_eq!(tent.evaluate(0.5, 0.0), 0.5);
+        let gaussian = Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        };
+        assert_eq!(gaussian.evaluate(1.5, 0.0), 0.0);
+        assert!(gaussian.evaluate(0.0, 0.0) > gaussian.evaluate(0.5, 0.0));
+    }
+
+    #[test]
+    fn negative_lobes_test() {
+        let mitchell = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        assert!((mitchell.evaluate_1d(0.0) - 8.0 / 9.0).abs() < 1e-6);
+        assert!(mitchell.evaluate_1d(1.5) < 0.0);
+        assert!(mitchell.evaluate_1d(1.999).abs() < 1e-3);
+        let lanczos = Filter::Lanczos { radius: 3.0 };
+        assert_eq!(lanczos.evaluate_1d(0.0), 1.0);
+        assert!(lanczos.evaluate_1d(1.0).abs() < 1e-6);
+        assert!(lanczos.evaluate_1d(1.5) < 0.0);
+    }
+}
@@ -0,0 +1,317 @@
+use super::{light, math, util};
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+
+/*
+Photometric profile read from an IES LM-63 file, giving the luminous intensity of a luminaire, in
+candelas, for every direction around it. Only type C photometry, used by nearly every architectural
+fixture, is supported: vertical angles go from 0° at the nadir to 180° at the zenith, and horizontal
+angles turn around the vertical axis.
+*/
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    vertical_angles: Vec<f32>,
+    horizontal_angles: Vec<f32>,
+    candelas: Vec<Vec<f32>>, // One list of values over the vertical angles per horizontal angle
+}
+
+impl IesProfile {
+    pub fn from_file(filepath: &str) -> io::Result<IesProfile> {
+        IesProfile::parse(&fs::read_to_string(filepath)?)
+    }
+
+    pub fn parse(contents: &str) -> io::Result<IesProfile> {
+        let mut lines = contents.lines();
+        let mut tilt = None;
+        for line in &mut lines {
+            let line = line.trim();
+            if let Some(value) = line.strip_prefix("TILT=") {
+                tilt = Some(value.trim().to_string());
+                break;
+            }
+        }
+        let tilt = tilt.ok_or_else(|| invalid_data("missing TILT line"))?;
+
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f32>()
+                    .map_err(|_| invalid_data(&format!("invalid number '{}'", token)))
+            });
+        let mut next = || -> io::Result<f32> {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(invalid_data("unexpected end of file")))
+        };
+
+        match tilt.as_str() {
+            "NONE" => (),
+            "INCLUDE" => {
+                // The tilt data only matters for lamps whose output changes with their inclination
+                let _lamp_to_luminaire_geometry = next()?;
+                let pairs = next()? as usize;
+                for _ in 0..pairs {
+                    next()?;
+                    next()?;
+                }
+            }
+            _ => {
+                return Err(invalid_data(
+                    "tilt data in a separate file is not supported",
+                ))
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as u32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let ballast_factor = next()?;
+        let _future_use = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(invalid_data("only type C photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid_data("the profile has no angles"));
+        }
+
+        // The counts come from the file, so the lists only grow with the values actually read
+        let mut vertical_angles = Vec::new();
+        for _ in 0..vertical_count {
+            vertical_angles.push(next()?);
+        }
+        let mut horizontal_angles = Vec::new();
+        for _ in 0..horizontal_count {
+            horizontal_angles.push(next()?);
+        }
+        let mut candelas = Vec::new();
+        for _ in 0..horizontal_count {
+            let mut values = Vec::new();
+            for _ in 0..vertical_count {
+                values.push(next()? * multiplier * ballast_factor);
+            }
+            candelas.push(values);
+        }
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candelas,
+        })
+    }
+
+    // Luminous intensity, in candelas, at the given angles in degrees
+    pub fn get_intensity(&self, vertical_angle: f32, horizontal_angle: f32) -> f32 {
+        let first_vertical = self.vertical_angles[0];
+        let last_vertical = self.vertical_angles[self.vertical_angles.len() - 1];
+        if vertical_angle < first_vertical || vertical_angle > last_vertical {
+            return 0.0;
+        }
+        let horizontal_angle = self.fold_horizontal_angle(horizontal_angle);
+        let (h0, h1, th) = interpolation_indices(&self.horizontal_angles, horizontal_angle);
+        let (v0, v1, tv) = interpolation_indices(&self.vertical_angles, vertical_angle);
+        let at_h0 = self.candelas[h0][v0] * (1.0 - tv) + self.candelas[h0][v1] * tv;
+        let at_h1 = self.candelas[h1][v0] * (1.0 - tv) + self.candelas[h1][v1] * tv;
+        at_h0 * (1.0 - th) + at_h1 * th
+    }
+
+    // Total luminous flux, in lumens, integrating the intensity over the sphere of directions
+    pub fn get_flux(&self) -> f32 {
+        let vertical_steps = 90;
+        let horizontal_steps = 72;
+        let mut flux = 0.0;
+        for i in 0..vertical_steps {
+            let theta = (i as f32 + 0.5) / vertical_steps as f32 * PI;
+            let solid_angle =
+                theta.sin() * (PI / vertical_steps as f32) * (2.0 * PI / horizontal_steps as f32);
+            for j in 0..horizontal_steps {
+                let phi = (j as f32 + 0.5) / horizontal_steps as f32 * 360.0;
+                flux += self.get_intensity(theta.to_degrees(), phi) * solid_angle;
+            }
+        }
+        flux
+    }
+
+    // Maps the angle into the range covered by the profile, according to its symmetry
+    fn fold_horizontal_angle(&self, angle: f32) -> f32 {
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let mut angle = angle.rem_euclid(360.0);
+        if self.horizontal_angles.len() == 1 {
+            // Symmetric around the vertical axis
+            return self.horizontal_angles[0];
+        }
+        if last <= 90.0 {
+            // Symmetric in each quadrant
+            if angle > 180.0 {
+                angle = 360.0 - angle;
+            }
+            if angle > 90.0 {
+                angle = 180.0 - angle;
+            }
+        } else if last <= 180.0 && self.horizontal_angles[0] == 0.0 {
+            // Symmetric about the 0-180 plane
+            if angle > 180.0 {
+                angle = 360.0 - angle;
+            }
+        } else if last <= 270.0 && self.horizontal_angles[0] == 90.0 {
+            // Symmetric about the 90-270 plane
+            if angle < 90.0 {
+                angle = 180.0 - angle;
+            } else if angle > 270.0 {
+                angle = 540.0 - angle;
+            }
+        }
+        angle
+    }
+}
+
+// Indices of the two values around `x` in the sorted `values`, with the weight of the second one
+fn interpolation_indices(values: &[f32], x: f32) -> (usize, usize, f32) {
+    let upper = values.partition_point(|&value| value <= x);
+    if upper == 0 {
+        return (0, 0, 0.0);
+    }
+    if upper >= values.len() {
+        let last = values.len() - 1;
+        return (last, last, 0.0);
+    }
+    let lower = upper - 1;
+    let span = values[upper] - values[lower];
+    let t = if span > 0.0 {
+        (x - values[lower]) / span
+    } else {
+        0.0
+    };
+    (lower, upper, t)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid IES file: {}", message),
+    )
+}
+
+/*
+Point light whose intensity in each direction follows an IES profile. The profile is aimed with
+`nadir`, the direction of the 0° vertical angle, and `zero_plane`, the direction of the 0° horizontal
+angle. The candelas are multiplied by `scale` to bring them into the units of the scene, and fall off
+with the square of the distance.
+*/
+pub struct IesLight {
+    position: math::Vector,
+    profile: IesProfile,
+    scale: f32,
+    nadir: math::Vector,
+    zero_plane: math::Vector,
+    side: math::Vector,
+}
+
+impl IesLight {
+    pub fn new(position: math::Vector, profile: IesProfile, scale: f32) -> IesLight {
+        IesLight {
+            position,
+            profile,
+            scale,
+            nadir: math::Vector::new(0.0, -1.0, 0.0),
+            zero_plane: math::Vector::new(1.0, 0.0, 0.0),
+            side: math::Vector::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    // Aims the luminaire, as for a spot light
+    pub fn with_orientation(mut self, nadir: math::Vector, zero_plane: math::Vector) -> IesLight {
+        let nadir = nadir.normalize();
+        let side = math::Vector::cross_product(nadir, zero_plane).normalize();
+        self.nadir = nadir;
+        self.zero_plane = math::Vector::cross_product(side, nadir);
+        self.side = side;
+        self
+    }
+
+    // Intensity, already scaled, emitted along `direction`, which points away from the light
+    fn get_intensity(&self, direction: &math::Vector) -> f32 {
+        let cos_vertical = math::Vector::dot_product(*direction, self.nadir).clamp(-1.0, 1.0);
+        let horizontal = math::Vector::dot_product(*direction, self.side)
+            .atan2(math::Vector::dot_product(*direction, self.zero_plane));
+        self.profile
+            .get_intensity(cos_vertical.acos().to_degrees(), horizontal.to_degrees())
+            * self.scale
+    }
+}
+
+impl light::Light for IesLight {
+    fn sample(&self, point: &math::Vector, _u: (f32, f32)) -> Option<light::LightSample> {
+        let to_light = self.position - *point;
+        let distance = to_light.magnitude();
+        let direction = to_light / distance;
+        let intensity = self.get_intensity(&-direction) / (distance * distance);
+        Some(light::LightSample {
+            direction,
+            distance,
+            radiance: util::Color::new(intensity, intensity, intensity),
+            pdf: 1.0,
+        })
+    }
+
+    fn get_power(&self) -> f32 {
+        self.profile.get_flux() * self.scale
+    }
+
+    fn get_bounds(&self) -> Option<(math::Vector, math::Vector)> {
+        Some((self.position, self.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] Sample downlight
+[MANUFAC] Lightpaths
+TILT=NONE
+1 1000 1.0 3 1 1 2 0.1 0.1 0.0
+1.0 1.0 20
+0 45 90
+0
+200 100 0
+";
+
+    #[test]
+    fn parse_ies_profile_test() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.get_intensity(0.0, 0.0), 200.0);
+        assert_eq!(profile.get_intensity(22.5, 130.0), 150.0);
+        assert_eq!(profile.get_intensity(120.0, 0.0), 0.0);
+
+        // Horizontal angles from 90° to 270° describe a profile symmetric about the 90-270 plane
+        let bilateral = DOWNLIGHT.replace("1.0 3 1 1", "1.0 2 3 1").replace(
+            "0 45 90\n0\n200 100 0",
+            "0 90\n90 180 270\n100 50\n200 100\n300 150",
+        );
+        let profile = IesProfile::parse(&bilateral).unwrap();
+        assert_eq!(profile.get_intensity(0.0, 0.0), 200.0);
+        assert_eq!(profile.get_intensity(0.0, 45.0), 150.0);
+        assert_eq!(profile.get_intensity(0.0, 315.0), 250.0);
+    }
+
+    #[test]
+    fn reject_truncated_ies_file_test() {
+        let truncated = &DOWNLIGHT[..DOWNLIGHT.len() - 6];
+        assert!(IesProfile::parse(truncated).is_err());
+        let huge_counts = DOWNLIGHT.replace("1.0 3 1 1", "1.0 1e30 1e30 1");
+        assert!(IesProfile::parse(&huge_counts).is_err());
+    }
+}
@@ -1,12 +1,54 @@
+use super::{exr, png, pnm, rgbe, util};
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::io::BufWriter;
-use std::ops::{Index, IndexMut};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    // ASCII PPM (P3), 8 bits per channel and without alpha
+    Ppm,
+    // Binary PPM (P6), 8 bits per channel and without alpha
+    BinaryPpm,
+    // Portable float map, keeping the values above 1.0 but not the alpha
+    Pfm,
+    // Radiance RGBE, keeping the values above 1.0 but not the alpha
+    Hdr,
+    Png(png::BitDepth),
+    // OpenEXR with a single unnamed layer, keeping the values above 1.0
+    Exr(exr::PixelType, exr::Compression),
+}
+
+impl Format {
+    /*
+    Format given by the extension of the file, with the most common options for each format. PPM
+    files stay in ASCII, and binary ones are written by giving `BinaryPpm` to `write_as`.
+    */
+    pub fn from_path(filepath: &str) -> Option<Format> {
+        let extension = Path::new(filepath).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(Format::Ppm),
+            "pfm" => Some(Format::Pfm),
+            "hdr" => Some(Format::Hdr),
+            "png" => Some(Format::Png(png::BitDepth::Eight)),
+            "exr" => Some(Format::Exr(exr::PixelType::Half, exr::Compression::Zip)),
+            _ => None,
+        }
+    }
+}
+
+/*
+Floating-point framebuffer holding linear radiance, which may go above 1.0. The pixels are stored
+row by row in a single buffer, with three channels (RGB) or four (RGBA) each. Values are only
+quantized when the image is written to a file with a limited range.
+*/
+#[derive(Clone, Debug)]
 pub struct Image {
     height: usize,
     width: usize,
-    pixelmap: Vec<Vec<Vec<u8>>>,
+    channels: usize,
+    pixels: Vec<f32>,
 }
 
 impl Image {
@@ -14,29 +56,66 @@ impl Image {
         Image {
             height: height,
             width: width,
-            pixelmap: vec![vec![vec![0; 3]; width]; height],
+            channels: 3,
+            pixels: vec![0.0; height * width * 3],
         }
     }
 
-    pub fn write(&self, filepath: &str) -> std::io::Result<()> {
-        let format = "P3";
-        let max_value: u8 = 255;
-        let file = File::create(filepath)?;
-        let mut buffer = BufWriter::new(file);
-
-        buffer.write_fmt(format_args!(
-            "{}\n{} {}\n{}\n",
-            format, self.width, self.height, max_value
-        ))?;
-        for i in 0..self.height as usize {
-            for j in 0..self.width as usize {
-                buffer.write_fmt(format_args!(
-                    "{} {} {}\n",
-                    self.pixelmap[i][j][0], self.pixelmap[i][j][1], self.pixelmap[i][j][2]
-                ))?;
-            }
+    // Adds an alpha channel, initialized to fully opaque
+    pub fn with_alpha(mut self) -> Image {
+        if self.channels == 4 {
+            return self;
         }
-        Ok(())
+        let mut pixels = Vec::with_capacity(self.height * self.width * 4);
+        for pixel in self.pixels.chunks(3) {
+            pixels.extend_from_slice(pixel);
+            pixels.push(1.0);
+        }
+        self.channels = 4;
+        self.pixels = pixels;
+        self
+    }
+
+    // Reads a PPM, PFM or Radiance HDR image, recognized from the first bytes of the file
+    pub fn read(filepath: &str) -> io::Result<Image> {
+        let mut reader = BufReader::new(File::open(filepath)?);
+        let magic_number = reader.fill_buf()?.get(..2).unwrap_or(&[]).to_vec();
+        match magic_number.as_slice() {
+            b"P3" | b"P6" | b"PF" | b"Pf" => pnm::read(&mut reader),
+            b"#?" => rgbe::read(&mut reader),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the format of '{}' is not supported", filepath),
+            )),
+        }
+    }
+
+    // Writes the image in the format given by the extension of the file
+    pub fn write(&self, filepath: &str) -> io::Result<()> {
+        match Format::from_path(filepath) {
+            Some(format) => self.write_as(filepath, format),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no image format matches the extension of '{}'", filepath),
+            )),
+        }
+    }
+
+    pub fn write_as(&self, filepath: &str, format: Format) -> io::Result<()> {
+        let mut buffer = BufWriter::new(File::create(filepath)?);
+        match format {
+            Format::Ppm => pnm::write_ascii_ppm(self, &mut buffer)?,
+            Format::BinaryPpm => pnm::write_binary_ppm(self, &mut buffer)?,
+            Format::Pfm => pnm::write_pfm(self, &mut buffer)?,
+            Format::Hdr => rgbe::write(self, &mut buffer)?,
+            Format::Png(bit_depth) => png::write(self, &mut buffer, bit_depth)?,
+            Format::Exr(pixel_type, compression) => exr::write(
+                &mut buffer,
+                &[exr::Layer::new("", self).with_pixel_type(pixel_type)],
+                compression,
+            )?,
+        }
+        buffer.flush()
     }
 
     pub fn get_height(&self) -> usize {
@@ -46,20 +125,70 @@ impl Image {
     pub fn get_width(&self) -> usize {
         self.width
     }
-}
 
-impl Index<usize> for Image {
-    type Output = Vec<Vec<u8>>;
+    pub fn get_channels(&self) -> usize {
+        self.channels
+    }
 
-    fn index(&self, i: usize) -> &Vec<Vec<u8>> {
-        &self.pixelmap[i]
+    pub fn has_alpha(&self) -> bool {
+        self.channels == 4
+    }
+
+    // Every channel of every pixel, row by row from the top left corner
+    pub fn get_data(&self) -> &[f32] {
+        &self.pixels
     }
-}
 
-impl IndexMut<usize> for Image {
-    fn index_mut(&mut self, i: usize) -> &mut Vec<Vec<u8>> {
-        &mut self.pixelmap[i]
+    pub fn get_pixel(&self, i: usize, j: usize) -> util::Color {
+        let offset = self.get_offset(i, j);
+        util::Color::new(
+            self.pixels[offset],
+            self.pixels[offset + 1],
+            self.pixels[offset + 2],
+        )
     }
+
+    pub fn set_pixel(&mut self, i: usize, j: usize, color: util::Color) {
+        let offset = self.get_offset(i, j);
+        self.pixels[offset] = color.r;
+        self.pixels[offset + 1] = color.g;
+        self.pixels[offset + 2] = color.b;
+    }
+
+    // Images without an alpha channel are fully opaque
+    pub fn get_alpha(&self, i: usize, j: usize) -> f32 {
+        if self.has_alpha() {
+            self.pixels[self.get_offset(i, j) + 3]
+        } else {
+            1.0
+        }
+    }
+
+    pub fn set_alpha(&mut self, i: usize, j: usize, alpha: f32) {
+        assert!(self.has_alpha(), "the image has no alpha channel");
+        let offset = self.get_offset(i, j);
+        self.pixels[offset + 3] = alpha;
+    }
+
+    fn get_offset(&self, i: usize, j: usize) -> usize {
+        assert!(
+            i < self.height && j < self.width,
+            "pixel ({}, {}) is outside the image",
+            i,
+            j
+        );
+        (i * self.width + j) * self.channels
+    }
+}
+
+// Integer code of a value in [0, 1] for a file storing values from 0 to `max_value`
+pub fn quantize(value: f32, max_value: u16) -> u16 {
+    let value = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    };
+    (value * max_value as f32).round() as u16
 }
 
 #[cfg(test)]
@@ -69,9 +198,38 @@ mod tests {
     #[test]
     fn pixel_color_assignment() {
         let mut image = Image::new(2, 2);
-        image[0][0][0] = 255;
-        image[0][0][1] = 127;
-        image[0][0][2] = 10;
-        assert_eq!(image[0][0], [255, 127, 10])
+        image.set_pixel(0, 1, util::Color::new(2.5, 0.5, 0.04));
+        assert_eq!(image.get_pixel(0, 1), util::Color::new(2.5, 0.5, 0.04));
+        assert_eq!(image.get_pixel(1, 0), util::Color::new(0.0, 0.0, 0.0));
+        assert_eq!(&image.get_data()[3..6], &[2.5, 0.5, 0.04]);
+    }
+
+    #[test]
+    fn alpha_channel_test() {
+        let mut image = Image::new(1, 2);
+        image.set_pixel(0, 1, util::Color::new(0.1, 0.2, 0.3));
+        let mut image = image.with_alpha();
+        assert_eq!(image.get_alpha(0, 1), 1.0);
+        image.set_alpha(0, 0, 0.5);
+        assert_eq!(image.get_data(), &[0.0, 0.0, 0.0, 0.5, 0.1, 0.2, 0.3, 1.0]);
+    }
+
+    #[test]
+    fn format_from_extension_test() {
+        assert_eq!(Format::from_path("spheres.ppm"), Some(Format::Ppm));
+        assert_eq!(Format::from_path("probe.hdr"), Some(Format::Hdr));
+        assert_eq!(
+            Format::from_path("renders/spheres.PNG"),
+            Some(Format::Png(png::BitDepth::Eight))
+        );
+        assert_eq!(Format::from_path("spheres"), None);
+    }
+
+    #[test]
+    fn quantization_clamps_to_the_output_range_test() {
+        assert_eq!(quantize(2.5, 255), 255);
+        assert_eq!(quantize(0.5, 255), 128);
+        assert_eq!(quantize(-1.0, 255), 0);
+        assert_eq!(quantize(1.0, 65535), 65535);
     }
 }
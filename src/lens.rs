@@ -0,0 +1,90 @@
+/*
+Brown-Conrady lens distortion, with radial coefficients k1, k2 and k3 and tangential coefficients p1
+and p2. It works on normalized coordinates, where (0, 0) is the optical center and a unit is one
+focal length, as used by camera calibration tools, so their coefficients can be copied directly.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+    pub k3: f32,
+    pub p1: f32,
+    pub p2: f32,
+}
+
+const UNDISTORTION_ITERATIONS: u32 = 20;
+
+impl Distortion {
+    pub fn new(k1: f32, k2: f32, k3: f32, p1: f32, p2: f32) -> Distortion {
+        Distortion { k1, k2, k3, p1, p2 }
+    }
+
+    // Where the lens moves a point of the ideal, undistorted image
+    pub fn distort(&self, x: f32, y: f32) -> (f32, f32) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    // Inverse of `Distortion::distort`, which has no closed form and is found by fixed-point iteration
+    pub fn undistort(&self, x: f32, y: f32) -> (f32, f32) {
+        let mut undistorted_x = x;
+        let mut undistorted_y = y;
+        for _ in 0..UNDISTORTION_ITERATIONS {
+            let r2 = undistorted_x * undistorted_x + undistorted_y * undistorted_y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let tangential_x = 2.0 * self.p1 * undistorted_x * undistorted_y
+                + self.p2 * (r2 + 2.0 * undistorted_x * undistorted_x);
+            let tangential_y = self.p1 * (r2 + 2.0 * undistorted_y * undistorted_y)
+                + 2.0 * self.p2 * undistorted_x * undistorted_y;
+            undistorted_x = (x - tangential_x) / radial;
+            undistorted_y = (y - tangential_y) / radial;
+        }
+        (undistorted_x, undistorted_y)
+    }
+}
+
+/*
+Optical vignetting following the cosine-fourth law, where the light reaching the sensor falls off
+with the angle to the optical axis. A strength of one applies the full falloff, zero disables it.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Vignetting {
+    pub strength: f32,
+}
+
+impl Vignetting {
+    pub fn new(strength: f32) -> Vignetting {
+        Vignetting { strength }
+    }
+
+    // Fraction of the light kept at the normalized coordinates (x, y)
+    pub fn get_falloff(&self, x: f32, y: f32) -> f32 {
+        let cos_theta_squared = 1.0 / (1.0 + x * x + y * y);
+        1.0 - self.strength * (1.0 - cos_theta_squared * cos_theta_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undistort_inverts_distort_test() {
+        let distortion = Distortion::new(-0.12, 0.03, -0.002, 0.001, -0.0005);
+        let (x, y) = distortion.distort(0.4, -0.25);
+        let (undistorted_x, undistorted_y) = distortion.undistort(x, y);
+        assert!((undistorted_x - 0.4).abs() < 1e-5);
+        assert!((undistorted_y + 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vignetting_darkens_the_corners_test() {
+        let vignetting = Vignetting::new(1.0);
+        assert_eq!(vignetting.get_falloff(0.0, 0.0), 1.0);
+        assert!((vignetting.get_falloff(1.0, 0.0) - 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod adaptive;
+pub mod aov;
+pub mod camera;
+pub mod deflate;
+pub mod exr;
+pub mod film;
+pub mod filter;
+pub mod ies;
+pub mod image;
+pub mod lens;
+pub mod light;
+pub mod light_sampler;
+pub mod math;
+pub mod motion;
+pub mod object;
+pub mod png;
+pub mod pnm;
+pub mod progress;
+pub mod rendering;
+pub mod rgbe;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+pub mod sky;
+pub mod stereo;
+pub mod tonemap;
+pub mod util;
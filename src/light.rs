@@ -1,15 +1,220 @@
-use super::math;
+use super::{math, sampling, util};
+use std::f32::consts::PI;
 
-pub struct Light {
+pub trait Light {
+    // `u` is a pair of uniform random numbers in [0, 1), used by lights that need to be sampled
+    fn sample(&self, point: &math::Vector, u: (f32, f32)) -> Option<LightSample>;
+
+    // Delta lights, like point and directional lights, can't be hit by rays and are only sampled
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    // Probability density, with respect to solid angle, of sampling `direction` from `point`
+    fn get_pdf(&self, _point: &math::Vector, _direction: &math::Vector) -> f32 {
+        0.0
+    }
+
+    // Distance to the light and radiance it emits towards the origin of the ray, if the ray hits it
+    fn get_radiance_along(&self, _ray: &util::Ray) -> Option<(f32, util::Color)> {
+        None
+    }
+
+    // Total emitted power, as luminance, used to choose between lights
+    fn get_power(&self) -> f32;
+
+    // Minimum and maximum corners of the box enclosing the light, None for lights at infinity
+    fn get_bounds(&self) -> Option<(math::Vector, math::Vector)> {
+        None
+    }
+}
+
+pub struct LightSample {
+    pub direction: math::Vector, // Normalized, pointing from the shaded point towards the light
+    pub distance: f32,
+    pub radiance: util::Color,
+    pub pdf: f32, // Always 1.0 for delta lights
+}
+
+// Light emitting `intensity` evenly in every direction, falling off with the square of the distance
+pub struct PointLight {
     pub position: math::Vector,
     pub intensity: f32,
 }
 
-impl Light {
-    pub fn new(p: math::Vector, i: f32) -> Light {
-        Light {
+impl PointLight {
+    pub fn new(p: math::Vector, i: f32) -> PointLight {
+        PointLight {
             position: p,
             intensity: i,
         }
     }
 }
+
+impl Light for PointLight {
+    fn sample(&self, point: &math::Vector, _u: (f32, f32)) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance = to_light.magnitude();
+        let intensity = self.intensity / (distance * distance);
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: util::Color::new(intensity, intensity, intensity),
+            pdf: 1.0,
+        })
+    }
+
+    fn get_power(&self) -> f32 {
+        4.0 * PI * self.intensity
+    }
+
+    fn get_bounds(&self) -> Option<(math::Vector, math::Vector)> {
+        Some((self.position, self.position))
+    }
+}
+
+pub struct DirectionalLight {
+    pub direction: math::Vector, // Direction in which the light travels
+    pub intensity: util::Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: math::Vector, intensity: util::Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            intensity,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &math::Vector, _u: (f32, f32)) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f32::MAX,
+            radiance: self.intensity,
+            pdf: 1.0,
+        })
+    }
+
+    fn get_power(&self) -> f32 {
+        self.intensity.get_luminance()
+    }
+}
+
+// Spherical area light emitting the same radiance from every point of its surface
+pub struct SphereLight {
+    pub center: math::Vector,
+    pub radius: f32,
+    pub radiance: util::Color,
+}
+
+impl SphereLight {
+    pub fn new(center: math::Vector, radius: f32, radiance: util::Color) -> SphereLight {
+        SphereLight {
+            center,
+            radius,
+            radiance,
+        }
+    }
+
+    // Cosine of the half-angle of the cone subtended by the sphere, seen from `point`
+    fn get_cos_theta_max(&self, point: &math::Vector) -> Option<f32> {
+        let distance_squared = (self.center - *point).magnitude().powi(2);
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).max(0.0).sqrt())
+    }
+
+    fn get_distance_along(&self, ray: &util::Ray) -> Option<f32> {
+        let direction = ray.direction.normalize();
+        let oc = ray.origin - self.center;
+        let b = math::Vector::dot_product(oc, direction);
+        let c = math::Vector::dot_product(oc, oc) - self.radius * self.radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t = -b - discriminant.sqrt();
+        if t > 0.0 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+// The sphere is sampled uniformly inside the cone it subtends, so only its visible side is chosen
+impl Light for SphereLight {
+    fn sample(&self, point: &math::Vector, u: (f32, f32)) -> Option<LightSample> {
+        let cos_theta_max = self.get_cos_theta_max(point)?;
+        let axis = (self.center - *point).normalize();
+        let direction = sampling::uniform_sample_cone(u, cos_theta_max).to_world(axis);
+        // Directions on the border of the cone can miss the sphere through rounding errors
+        let distance = self
+            .get_distance_along(&util::Ray::new(*point, direction))
+            .unwrap_or_else(|| (self.center - *point).magnitude());
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.radiance,
+            pdf: sampling::uniform_cone_pdf(cos_theta_max),
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn get_pdf(&self, point: &math::Vector, direction: &math::Vector) -> f32 {
+        match self.get_cos_theta_max(point) {
+            Some(cos_theta_max) => {
+                let axis = (self.center - *point).normalize();
+                if math::Vector::dot_product(axis, direction.normalize()) >= cos_theta_max {
+                    sampling::uniform_cone_pdf(cos_theta_max)
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    fn get_radiance_along(&self, ray: &util::Ray) -> Option<(f32, util::Color)> {
+        self.get_distance_along(ray)
+            .map(|distance| (distance, self.radiance))
+    }
+
+    fn get_power(&self) -> f32 {
+        PI * 4.0 * PI * self.radius * self.radius * self.radiance.get_luminance()
+    }
+
+    fn get_bounds(&self) -> Option<(math::Vector, math::Vector)> {
+        let extent = math::Vector::new(self.radius, self.radius, self.radius);
+        Some((self.center - extent, self.center + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_light_sample_test() {
+        let light = SphereLight::new(
+            math::Vector::new(0.0, 2.0, 0.0),
+            0.5,
+            util::Color::new(1.0, 1.0, 1.0),
+        );
+        let point = math::Vector::new(0.0, 0.0, 0.0);
+        let sample = light.sample(&point, (0.3, 0.6)).unwrap();
+        assert!(sample.distance > 1.4 && sample.distance < 2.0);
+        assert!((light.get_pdf(&point, &sample.direction) - sample.pdf).abs() < 1e-4);
+        assert_eq!(
+            light.get_pdf(&point, &math::Vector::new(1.0, 0.0, 0.0)),
+            0.0
+        );
+    }
+}
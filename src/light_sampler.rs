@@ -0,0 +1,247 @@
+use super::{light, math, sampling};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Strategy {
+    // Every light is sampled at every shading point
+    All,
+    // A single light is chosen in proportion to its power
+    Power,
+    // A single light is chosen by descending a hierarchy of lights, favoring the bright and close ones
+    Bvh,
+}
+
+/*
+Chooses which lights are sampled at a shading point. Lights at infinity, like the sun or the sky,
+can't be bounded and are few in practice, so they are always sampled. With the `Power` and `Bvh`
+strategies a single light is picked among the remaining ones, and its contribution must be divided
+by the probability of picking it, which keeps the cost per shading point constant.
+*/
+pub struct LightSampler {
+    strategy: Strategy,
+    infinite_lights: Vec<usize>,
+    bounded_lights: Vec<usize>,
+    distribution: sampling::Distribution1D,
+    bvh: Option<Node>,
+}
+
+impl LightSampler {
+    pub fn new(lights: &[Box<dyn light::Light>], strategy: Strategy) -> LightSampler {
+        let mut infinite_lights = Vec::new();
+        let mut bounded_lights = Vec::new();
+        let mut leaves = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            match light.get_bounds() {
+                Some((min, max)) => {
+                    bounded_lights.push(i);
+                    leaves.push(Node::new_leaf(i, min, max, light.get_power()));
+                }
+                None => infinite_lights.push(i),
+            }
+        }
+        let powers: Vec<f32> = bounded_lights
+            .iter()
+            .map(|&i| lights[i].get_power())
+            .collect();
+        let bvh = if strategy == Strategy::Bvh && !leaves.is_empty() {
+            Some(Node::build(leaves))
+        } else {
+            None
+        };
+
+        LightSampler {
+            strategy,
+            infinite_lights,
+            bounded_lights,
+            distribution: sampling::Distribution1D::new(&powers),
+            bvh,
+        }
+    }
+
+    // Indices of the lights to sample at `point`, each with the probability it was chosen with
+    pub fn select(&self, point: &math::Vector, u: f32) -> Vec<(usize, f32)> {
+        let mut selected: Vec<(usize, f32)> =
+            self.infinite_lights.iter().map(|&i| (i, 1.0)).collect();
+        match self.strategy {
+            Strategy::All => {
+                selected.extend(self.bounded_lights.iter().map(|&i| (i, 1.0)));
+            }
+            Strategy::Power => {
+                if let Some((index, pmf)) = self.distribution.sample_discrete(u) {
+                    if pmf > 0.0 {
+                        selected.push((self.bounded_lights[index], pmf));
+                    }
+                }
+            }
+            Strategy::Bvh => {
+                if let Some(bvh) = &self.bvh {
+                    if let Some((index, pmf)) = bvh.select(point, u) {
+                        selected.push((index, pmf));
+                    }
+                }
+            }
+        }
+        selected
+    }
+}
+
+struct Node {
+    min: math::Vector,
+    max: math::Vector,
+    power: f32,
+    content: NodeContent,
+}
+
+enum NodeContent {
+    Leaf(usize),
+    Interior(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn new_leaf(light_index: usize, min: math::Vector, max: math::Vector, power: f32) -> Node {
+        Node {
+            min,
+            max,
+            power: power.max(0.0),
+            content: NodeContent::Leaf(light_index),
+        }
+    }
+
+    // Splits the nodes at the median of their centers, along the axis where the centers spread most
+    fn build(mut nodes: Vec<Node>) -> Node {
+        if nodes.len() == 1 {
+            return nodes.remove(0);
+        }
+        let centers: Vec<math::Vector> = nodes.iter().map(|node| node.get_center()).collect();
+        let mut low = centers[0];
+        let mut high = centers[0];
+        for center in centers.iter() {
+            low = math::Vector::new(
+                low.x.min(center.x),
+                low.y.min(center.y),
+                low.z.min(center.z),
+            );
+            high = math::Vector::new(
+                high.x.max(center.x),
+                high.y.max(center.y),
+                high.z.max(center.z),
+            );
+        }
+        let extent = high - low;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let coordinate = |v: math::Vector| match axis {
+            0 => v.x,
+            1 => v.y,
+            _ => v.z,
+        };
+        nodes.sort_by(|a, b| {
+            coordinate(a.get_center())
+                .partial_cmp(&coordinate(b.get_center()))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let right_nodes = nodes.split_off(nodes.len() / 2);
+        let left = Node::build(nodes);
+        let right = Node::build(right_nodes);
+
+        Node {
+            min: math::Vector::new(
+                left.min.x.min(right.min.x),
+                left.min.y.min(right.min.y),
+                left.min.z.min(right.min.z),
+            ),
+            max: math::Vector::new(
+                left.max.x.max(right.max.x),
+                left.max.y.max(right.max.y),
+                left.max.z.max(right.max.z),
+            ),
+            power: left.power + right.power,
+            content: NodeContent::Interior(Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn get_center(&self) -> math::Vector {
+        (self.min + self.max) / 2.0
+    }
+
+    /*
+    Importance of the lights inside the node as seen from `point`: their power divided by the squared
+    distance to the node, which is clamped to the size of the node so that a point inside or next to
+    it doesn't give it an unbounded importance.
+    */
+    fn get_importance(&self, point: &math::Vector) -> f32 {
+        let radius_squared = ((self.max - self.min) / 2.0).magnitude().powi(2);
+        let distance_squared = (self.get_center() - *point).magnitude().powi(2);
+        self.power / distance_squared.max(radius_squared).max(f32::EPSILON)
+    }
+
+    // Descends the tree, reusing `u` at every level after rescaling it to the chosen child
+    fn select(&self, point: &math::Vector, u: f32) -> Option<(usize, f32)> {
+        let mut node = self;
+        let mut u = u;
+        let mut pmf = 1.0;
+        loop {
+            match &node.content {
+                NodeContent::Leaf(index) => return Some((*index, pmf)),
+                NodeContent::Interior(left, right) => {
+                    let left_importance = left.get_importance(point);
+                    let right_importance = right.get_importance(point);
+                    let total = left_importance + right_importance;
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let left_probability = left_importance / total;
+                    if u < left_probability {
+                        u /= left_probability;
+                        pmf *= left_probability;
+                        node = left;
+                    } else {
+                        u = (u - left_probability) / (1.0 - left_probability);
+                        pmf *= 1.0 - left_probability;
+                        node = right;
+                    }
+                    u = u.min(1.0 - f32::EPSILON);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn bvh_selection_favors_closer_lights_test() {
+        let lights: Vec<Box<dyn light::Light>> = vec![
+            Box::new(light::PointLight::new(
+                math::Vector::new(0.0, 1.0, 0.0),
+                1.0,
+            )),
+            Box::new(light::PointLight::new(
+                math::Vector::new(10.0, 1.0, 0.0),
+                1.0,
+            )),
+            Box::new(light::PointLight::new(
+                math::Vector::new(20.0, 1.0, 0.0),
+                1.0,
+            )),
+        ];
+        let sampler = LightSampler::new(&lights, Strategy::Bvh);
+        let point = math::Vector::new(0.0, 0.0, 0.0);
+        let mut pmfs = HashMap::new();
+        for i in 0..1000 {
+            for (index, pmf) in sampler.select(&point, i as f32 / 1000.0) {
+                pmfs.insert(index, pmf);
+            }
+        }
+        let total: f32 = pmfs.values().sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!(pmfs[&0] > pmfs[&1] && pmfs[&1] > pmfs[&2]);
+    }
+}
@@ -1,26 +1,22 @@
-extern crate rand;
-
+use lightpaths::{
+    adaptive, camera, filter, light, math, object, progress, rendering, sampler, scene, tonemap,
+    util,
+};
+use std::io::Write;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
 use std::time::SystemTime;
 
-mod camera;
-mod image;
-mod light;
-mod math;
-mod object;
-mod rendering;
-mod scene;
-mod util;
-
 fn main() {
     // Creating camera
     let position = math::Vector::new(0.0, 0.0, -0.4);
     let direction = math::Vector::new(0.0, 0.0, 1.0);
-    let camera = camera::Camera::new(
+    let camera = camera::PerspectiveCamera::new(
         position,
         direction,
-        1.0,
+        0.6,
         camera::Dimensions::new(0.45, 0.8),
-        camera::Dimensions::new(720 as f32, 1280 as f32),
+        camera::Dimensions::new(720_f32, 1280_f32),
     );
 
     // Creating surfaces for the objects
@@ -50,7 +46,7 @@ fn main() {
         math::Vector::new(0.0, -0.4, 0.0),
         blue_surface,
     );
-    let objects: Vec<Box<object::Object>> = vec![
+    let objects: Vec<Box<dyn object::Object>> = vec![
         Box::new(green_sphere),
         Box::new(red_sphere),
         Box::new(orange_sphere),
@@ -58,20 +54,36 @@ fn main() {
     ];
 
     // Creating lights
-    let right_light = light::Light::new(math::Vector::new(-0.8, 0.5, 0.0), 0.8);
-    let left_light = light::Light::new(math::Vector::new(0.8, 0.5, 0.0), 0.8);
-    let lights: Vec<light::Light> = vec![left_light];
+    let left_light = light::PointLight::new(math::Vector::new(0.8, 0.5, 0.0), 1.6);
+    let lights: Vec<Box<dyn light::Light>> = vec![Box::new(left_light)];
 
     // Creating scene
-    let scene = scene::Scene::new(lights, objects);
+    let background = scene::Background::Color(util::Color::new(0.0, 0.0, 0.0));
+    let scene = scene::Scene::new(lights, objects, background);
 
     // Creating renderer
-    let renderer = rendering::Renderer::new(camera, scene);
+    let display_transform =
+        tonemap::DisplayTransform::new(tonemap::ToneMapper::Aces, tonemap::TransferFunction::Srgb);
+    let filter = filter::Filter::Mitchell {
+        radius: 2.0,
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0,
+    };
+    let cancellation_token = get_cancellation_token();
+    let renderer = rendering::Renderer::new(Box::new(camera), scene)
+        .with_sampler(Box::new(sampler::SobolSampler::new(100)))
+        .with_adaptive_sampling(adaptive::AdaptiveSampling::new(16, 0.02))
+        .with_filter(filter)
+        .with_display_transform(display_transform)
+        .with_progress_callback(get_progress_printer())
+        .with_cancellation_token(cancellation_token.clone());
 
+    println!("Press Enter to stop the render and keep the samples taken so far");
     let time_now = SystemTime::now();
     let rendering_result = renderer.render();
     match time_now.elapsed() {
         Ok(elapsed) => {
+            println!();
             println!(
                 "Elapsed time during rendering: {} seconds",
                 elapsed.as_secs()
@@ -82,7 +94,11 @@ fn main() {
         }
     }
 
-    match rendering_result.write("spheres.ppm") {
+    // A stopped render still gives the image of the passes it finished
+    if cancellation_token.is_cancelled() {
+        println!("Render stopped, writing the samples taken so far");
+    }
+    match rendering_result.write("spheres.png") {
         Ok(result) => result,
         Err(error) => {
             panic!(
@@ -92,3 +108,40 @@ fn main() {
         }
     }
 }
+
+// Token cancelled when Enter is pressed, from a thread waiting on the standard input
+fn get_cancellation_token() -> progress::CancellationToken {
+    let token = progress::CancellationToken::new();
+    let watcher_token = token.clone();
+    thread::spawn(move || {
+        let mut line = String::new();
+        // A closed standard input, as when the renderer runs in the background, doesn't stop it
+        if let Ok(1..) = std::io::stdin().read_line(&mut line) {
+            watcher_token.cancel();
+        }
+    });
+    token
+}
+
+// Prints the progress of the render on a single line, whenever its percentage changes
+fn get_progress_printer() -> progress::ProgressCallback {
+    let last_percentage = AtomicI32::new(-1);
+    Box::new(move |progress: &progress::Progress| {
+        let percentage = progress.get_percentage() as i32;
+        if last_percentage.swap(percentage, Ordering::Relaxed) == percentage {
+            return;
+        }
+        let remaining = match progress.remaining {
+            Some(remaining) => format!("{} s", remaining.as_secs()),
+            None => "unknown".to_string(),
+        };
+        print!(
+            "\rRendering: {}% (pass {}, {:.1} Mrays/s, {} left)   ",
+            percentage,
+            progress.passes + 1,
+            progress.rays_per_second / 1e6,
+            remaining
+        );
+        let _ = std::io::stdout().flush();
+    })
+}
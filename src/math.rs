@@ -32,6 +32,24 @@ impl Vector {
     pub fn magnitude(self) -> f32 {
         (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
     }
+
+    // Two vectors that form an orthonormal basis with this one, which must be normalized
+    pub fn orthonormal_basis(self) -> (Vector, Vector) {
+        // Uses the method from Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vector::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vector::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
+    // Transforms a vector from the local frame whose z axis is `axis` to world coordinates
+    pub fn to_world(self, axis: Vector) -> Vector {
+        let (t, b) = axis.orthonormal_basis();
+        t * self.x + b * self.y + axis * self.z
+    }
 }
 
 impl Add for Vector {
@@ -94,6 +112,93 @@ impl Neg for Vector {
     }
 }
 
+// Unit quaternion representing a rotation
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn identity() -> Quaternion {
+        Quaternion {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    // Rotation by `angle` degrees around `axis`, counter-clockwise when looking down the axis
+    pub fn from_axis_angle(axis: Vector, angle: f32) -> Quaternion {
+        let axis = axis.normalize();
+        let half_angle = angle.to_radians() / 2.0;
+        let sin = half_angle.sin();
+        Quaternion {
+            w: half_angle.cos(),
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+        }
+    }
+
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn rotate(self, v: Vector) -> Vector {
+        let q = Vector::new(self.x, self.y, self.z);
+        let t = Vector::cross_product(q, v) * 2.0;
+        v + t * self.w + Vector::cross_product(q, t)
+    }
+
+    // Spherical linear interpolation, following the shortest path between the two rotations
+    pub fn slerp(q1: Quaternion, q2: Quaternion, t: f32) -> Quaternion {
+        let mut cos_theta = q1.w * q2.w + q1.x * q2.x + q1.y * q2.y + q1.z * q2.z;
+        let mut q2 = q2;
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            q2 = Quaternion {
+                w: -q2.w,
+                x: -q2.x,
+                y: -q2.y,
+                z: -q2.z,
+            };
+        }
+        let (a, b) = if cos_theta > 0.9995 {
+            // Nearly identical rotations, where linear interpolation is accurate and stable
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+        let q = Quaternion {
+            w: a * q1.w + b * q2.w,
+            x: a * q1.x + b * q2.x,
+            y: a * q1.y + b * q2.y,
+            z: a * q1.z + b * q2.z,
+        };
+        let norm = (q.w * q.w + q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        Quaternion {
+            w: q.w / norm,
+            x: q.x / norm,
+            y: q.y / norm,
+            z: q.z / norm,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +249,27 @@ mod tests {
         assert_eq!(v1.normalize(), Vector::new(1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn orthonormal_basis_test() {
+        let n = Vector::new(1.0, 2.0, -2.0).normalize();
+        let (t, b) = n.orthonormal_basis();
+        assert!(Vector::dot_product(n, t).abs() < 1e-6);
+        assert!(Vector::dot_product(n, b).abs() < 1e-6);
+        assert!(Vector::dot_product(t, b).abs() < 1e-6);
+        assert!((t.magnitude() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn quaternion_rotation_test() {
+        let q = Quaternion::from_axis_angle(Vector::new(0.0, 1.0, 0.0), 90.0);
+        let rotated = q.rotate(Vector::new(1.0, 0.0, 0.0));
+        assert!((rotated - Vector::new(0.0, 0.0, -1.0)).magnitude() < 1e-6);
+        let halfway = Quaternion::slerp(Quaternion::identity(), q, 0.5);
+        let rotated = halfway.rotate(Vector::new(1.0, 0.0, 0.0));
+        let expected = Vector::new(1.0, 0.0, -1.0).normalize();
+        assert!((rotated - expected).magnitude() < 1e-6);
+    }
+
     #[test]
     fn magnitude_of_vector() {
         let v1 = Vector::new(2.0, 2.0, 1.0);
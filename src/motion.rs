@@ -0,0 +1,116 @@
+use super::math;
+
+// Placement of an object at a given time: scaled uniformly, then rotated, then translated
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: math::Vector,
+    pub rotation: math::Quaternion,
+    pub scale: f32,
+}
+
+impl Keyframe {
+    pub fn new(
+        time: f32,
+        translation: math::Vector,
+        rotation: math::Quaternion,
+        scale: f32,
+    ) -> Keyframe {
+        Keyframe {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn transform_point(&self, point: math::Vector) -> math::Vector {
+        self.rotation.rotate(point * self.scale) + self.translation
+    }
+
+    pub fn inverse_transform_point(&self, point: math::Vector) -> math::Vector {
+        self.rotation.conjugate().rotate(point - self.translation) / self.scale
+    }
+
+    pub fn inverse_transform_vector(&self, vector: math::Vector) -> math::Vector {
+        self.rotation.conjugate().rotate(vector) / self.scale
+    }
+
+    // Uniform scaling leaves the normals unchanged, so they only need to be rotated
+    pub fn transform_normal(&self, normal: math::Vector) -> math::Vector {
+        self.rotation.rotate(normal)
+    }
+}
+
+pub enum Motion {
+    // Constant velocity, in units of the scene per unit of time, starting from the original position at time zero
+    Linear(math::Vector),
+    // Placements sorted by time, interpolated between them and held before the first and after the last
+    Keyframes(Vec<Keyframe>),
+}
+
+impl Motion {
+    pub fn get_keyframe_at(&self, time: f32) -> Keyframe {
+        match self {
+            Motion::Linear(velocity) => {
+                Keyframe::new(time, *velocity * time, math::Quaternion::identity(), 1.0)
+            }
+            Motion::Keyframes(keyframes) => {
+                if keyframes.is_empty() {
+                    return Keyframe::new(
+                        time,
+                        math::Vector::new(0.0, 0.0, 0.0),
+                        math::Quaternion::identity(),
+                        1.0,
+                    );
+                }
+                let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+                if next == 0 {
+                    return keyframes[0];
+                }
+                if next == keyframes.len() {
+                    return keyframes[keyframes.len() - 1];
+                }
+                let k0 = &keyframes[next - 1];
+                let k1 = &keyframes[next];
+                let t = (time - k0.time) / (k1.time - k0.time);
+                Keyframe::new(
+                    time,
+                    k0.translation * (1.0 - t) + k1.translation * t,
+                    math::Quaternion::slerp(k0.rotation, k1.rotation, t),
+                    k0.scale * (1.0 - t) + k1.scale * t,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyframe_interpolation_test() {
+        let motion = Motion::Keyframes(vec![
+            Keyframe::new(
+                0.0,
+                math::Vector::new(0.0, 0.0, 0.0),
+                math::Quaternion::identity(),
+                1.0,
+            ),
+            Keyframe::new(
+                1.0,
+                math::Vector::new(2.0, 0.0, 0.0),
+                math::Quaternion::identity(),
+                3.0,
+            ),
+        ]);
+        let keyframe = motion.get_keyframe_at(0.5);
+        assert_eq!(keyframe.translation, math::Vector::new(1.0, 0.0, 0.0));
+        assert_eq!(keyframe.scale, 2.0);
+        assert_eq!(motion.get_keyframe_at(4.0).scale, 3.0);
+        let point = math::Vector::new(0.5, 1.0, -2.0);
+        let roundtrip = keyframe.inverse_transform_point(keyframe.transform_point(point));
+        assert!((roundtrip - point).magnitude() < 1e-6);
+    }
+}
@@ -1,20 +1,21 @@
-use super::{light, math, util};
+use super::{math, motion, sampling, util};
+use std::f32::consts::PI;
+
+const DEFAULT_SHININESS: f32 = 50.0;
 
 pub trait Object {
     fn is_intersected_by(&self, ray: &util::Ray) -> bool;
     fn get_point_intersected_by(&self, ray: &util::Ray) -> Option<math::Vector>;
-    fn compute_color(
-        &self,
-        point: &math::Vector,
-        ray: &util::Ray,
-        light: &light::Light,
-    ) -> util::Color;
+    fn get_normal_at(&self, point: &math::Vector, time: f32) -> math::Vector;
+    fn get_surface(&self) -> &Surface;
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct Surface {
     diffuse_constant: math::Vector,
     specular_constant: math::Vector,
+    shininess: f32,
+    transmittance: math::Vector,
 }
 
 impl Surface {
@@ -22,6 +23,138 @@ impl Surface {
         Surface {
             diffuse_constant: dc,
             specular_constant: sc,
+            shininess: DEFAULT_SHININESS,
+            transmittance: math::Vector::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Higher values give sharper highlights, making the surface glossier
+    pub fn with_shininess(mut self, shininess: f32) -> Surface {
+        self.shininess = shininess;
+        self
+    }
+
+    // Fraction of the light passing through the surface, per channel. Surfaces are opaque by default.
+    pub fn with_transmittance(mut self, transmittance: math::Vector) -> Surface {
+        self.transmittance = transmittance;
+        self
+    }
+
+    pub fn get_transmittance(&self) -> util::Color {
+        util::Color::from(self.transmittance)
+    }
+
+    pub fn get_albedo(&self) -> util::Color {
+        util::Color::from(self.diffuse_constant)
+    }
+
+    /*
+    Energy-normalized Blinn-Phong BRDF multiplied by the cosine of the angle between the normal and
+    the incident direction. `wo` points towards the viewer and `wi` towards the incoming light, both
+    normalized.
+    */
+    pub fn evaluate(
+        &self,
+        normal: &math::Vector,
+        wo: &math::Vector,
+        wi: &math::Vector,
+    ) -> util::Color {
+        let (diffuse, specular) = self.evaluate_lobes(normal, wo, wi);
+        diffuse + specular
+    }
+
+    // Diffuse and specular parts of `Surface::evaluate`
+    pub fn evaluate_lobes(
+        &self,
+        normal: &math::Vector,
+        wo: &math::Vector,
+        wi: &math::Vector,
+    ) -> (util::Color, util::Color) {
+        let black = util::Color::new(0.0, 0.0, 0.0);
+        let cos_theta_i = math::Vector::dot_product(*normal, *wi);
+        let cos_theta_o = math::Vector::dot_product(*normal, *wo);
+        if cos_theta_i <= 0.0 || cos_theta_o <= 0.0 {
+            return (black, black);
+        }
+        let half_vector = (*wi + *wo).normalize();
+        let cos_theta_h = math::Vector::dot_product(*normal, half_vector).max(0.0);
+        let diffuse = self.diffuse_constant / PI;
+        let specular = self.specular_constant
+            * ((self.shininess + 2.0) / (2.0 * PI) * cos_theta_h.powf(self.shininess));
+        (
+            util::Color::from(diffuse) * cos_theta_i,
+            util::Color::from(specular) * cos_theta_i,
+        )
+    }
+
+    /*
+    Samples an incident direction, either from the cosine-weighted diffuse lobe or from the specular
+    lobe, chosen in proportion to their constants. Returns the direction with its probability density.
+    */
+    pub fn sample(
+        &self,
+        normal: &math::Vector,
+        wo: &math::Vector,
+        u: (f32, f32),
+    ) -> Option<(math::Vector, f32)> {
+        let specular_probability = self.get_specular_probability();
+        let wi = if u.0 < specular_probability {
+            let u = (u.0 / specular_probability, u.1);
+            let cos_theta_h = u.0.powf(1.0 / (self.shininess + 1.0));
+            let sin_theta_h = (1.0 - cos_theta_h * cos_theta_h).max(0.0).sqrt();
+            let phi = 2.0 * PI * u.1;
+            let half_vector = math::Vector::new(
+                sin_theta_h * phi.cos(),
+                sin_theta_h * phi.sin(),
+                cos_theta_h,
+            )
+            .to_world(*normal);
+            half_vector * (2.0 * math::Vector::dot_product(*wo, half_vector)) - *wo
+        } else {
+            let u = (
+                (u.0 - specular_probability) / (1.0 - specular_probability),
+                u.1,
+            );
+            sampling::cosine_sample_hemisphere(u).to_world(*normal)
+        };
+        if math::Vector::dot_product(*normal, wi) <= 0.0 {
+            return None;
+        }
+        let pdf = self.get_pdf(normal, wo, &wi);
+        if pdf > 0.0 {
+            Some((wi, pdf))
+        } else {
+            None
+        }
+    }
+
+    // Probability density of `Surface::sample` returning `wi`
+    pub fn get_pdf(&self, normal: &math::Vector, wo: &math::Vector, wi: &math::Vector) -> f32 {
+        let cos_theta_i = math::Vector::dot_product(*normal, *wi);
+        if cos_theta_i <= 0.0 {
+            return 0.0;
+        }
+        let half_vector = (*wi + *wo).normalize();
+        let cos_theta_h = math::Vector::dot_product(*normal, half_vector).max(0.0);
+        let wo_dot_h = math::Vector::dot_product(*wo, half_vector);
+        let specular_pdf = if wo_dot_h > 0.0 {
+            (self.shininess + 1.0) / (2.0 * PI) * cos_theta_h.powf(self.shininess)
+                / (4.0 * wo_dot_h)
+        } else {
+            0.0
+        };
+        let diffuse_pdf = cos_theta_i / PI;
+        let specular_probability = self.get_specular_probability();
+        specular_probability * specular_pdf + (1.0 - specular_probability) * diffuse_pdf
+    }
+
+    fn get_specular_probability(&self) -> f32 {
+        let diffuse = util::Color::from(self.diffuse_constant).get_luminance();
+        let specular = util::Color::from(self.specular_constant).get_luminance();
+        if diffuse + specular > 0.0 {
+            specular / (diffuse + specular)
+        } else {
+            0.0
         }
     }
 }
@@ -40,27 +173,11 @@ impl Sphere {
             surface: surface,
         }
     }
-
-    fn compute_normal(&self, point: &math::Vector) -> math::Vector {
-        let normal = (*point - self.center) / self.radius;
-        return normal;
-    }
 }
 
 impl Object for Sphere {
     fn is_intersected_by(&self, ray: &util::Ray) -> bool {
-        let oc = ray.origin - self.center;
-        let a = math::Vector::dot_product(ray.direction, ray.direction);
-        let b = math::Vector::dot_product(oc, ray.direction) * 2.0;
-        let c = math::Vector::dot_product(oc, oc) - self.radius.powi(2);
-        let discriminant = b * b - a * c * 4.0;
-        let t = (-b - discriminant.sqrt()) / (2.0 * a);
-        let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-        let mut is_intersected = false;
-        if t > 0.0 && t2 > 0.0 {
-            is_intersected = true;
-        }
-        return is_intersected;
+        self.get_point_intersected_by(ray).is_some()
     }
 
     fn get_point_intersected_by(&self, ray: &util::Ray) -> Option<math::Vector> {
@@ -83,28 +200,12 @@ impl Object for Sphere {
         return result;
     }
 
-    fn compute_color(
-        &self,
-        point: &math::Vector,
-        ray: &util::Ray,
-        light: &light::Light,
-    ) -> util::Color {
-        let normal = self.compute_normal(point);
-
-        let v = -ray.direction;
-        let half_vector = ((light.position - *point).normalize() + v).normalize();
-        let specular = self.surface.specular_constant
-            * light.intensity
-            * math::Vector::dot_product(normal, half_vector)
-                .powf(50.0)
-                .max(0.0);
-
-        let diffuse = self.surface.diffuse_constant
-            * light.intensity
-            * math::Vector::dot_product(normal, (light.position - *point).normalize()).max(0.0);
-        let illumination = diffuse + specular;
-        let color = util::Color::new(illumination.x, illumination.y, illumination.z);
-        return color;
+    fn get_normal_at(&self, point: &math::Vector, _time: f32) -> math::Vector {
+        (*point - self.center) / self.radius
+    }
+
+    fn get_surface(&self) -> &Surface {
+        &self.surface
     }
 }
 
@@ -126,10 +227,7 @@ impl Plane {
 
 impl Object for Plane {
     fn is_intersected_by(&self, ray: &util::Ray) -> bool {
-        let vd = math::Vector::dot_product(self.normal, ray.direction);
-        let vo = math::Vector::dot_product(self.normal, ray.origin);
-        let t = -(vo + self.distance_to_origin) / vd;
-        return t > 0.0;
+        self.get_point_intersected_by(ray).is_some()
     }
 
     fn get_point_intersected_by(&self, ray: &util::Ray) -> Option<math::Vector> {
@@ -146,34 +244,17 @@ impl Object for Plane {
         return result;
     }
 
-    fn compute_color(
-        &self,
-        point: &math::Vector,
-        ray: &util::Ray,
-        light: &light::Light,
-    ) -> util::Color {
-        let v = -ray.direction;
-        let half_vector = ((light.position - *point).normalize() + v).normalize();
-        let specular = self.surface.specular_constant
-            * light.intensity
-            * math::Vector::dot_product(self.normal, half_vector)
-                .powf(50.0)
-                .max(0.0);
-
-        let diffuse = self.surface.diffuse_constant
-            * light.intensity
-            * math::Vector::dot_product(self.normal, (light.position - *point).normalize())
-                .max(0.0);
-        let illumination = diffuse + specular;
-        let color = util::Color::new(illumination.x, illumination.y, illumination.z);
-        return color;
+    fn get_normal_at(&self, _point: &math::Vector, _time: f32) -> math::Vector {
+        self.normal
+    }
+
+    fn get_surface(&self) -> &Surface {
+        &self.surface
     }
 }
 
 pub struct Triangle {
     p0: math::Vector,
-    p1: math::Vector,
-    p2: math::Vector,
     u: math::Vector,
     v: math::Vector,
     pub normal: math::Vector,
@@ -192,8 +273,6 @@ impl Triangle {
 
         Triangle {
             p0: p0,
-            p1: p1,
-            p2: p2,
             u: u,
             v: v,
             normal: normal,
@@ -204,45 +283,21 @@ impl Triangle {
 
 impl Object for Triangle {
     fn is_intersected_by(&self, ray: &util::Ray) -> bool {
-        // Implements the Möller–Trumbore intersection algorithm
-        let h = math::Vector::cross_product(ray.direction, self.v);
-        let a = math::Vector::dot_product(self.u, h);
-        if a > -std::f32::EPSILON && a < std::f32::EPSILON {
-            return false;
-        }
-        let f = 1.0 / a;
-        let s = (ray.origin - self.p0);
-        let u = f * (math::Vector::dot_product(s, h));
-        // Check if the ray and the triangle are parallel
-        if u < 0.0 || u > 1.0 {
-            return false;
-        }
-        let q = math::Vector::cross_product(s, self.u);
-        let v = f * math::Vector::dot_product(ray.direction, q);
-        if v < 0.0 || (u + v) > 1.0 {
-            return false;
-        }
-        let t = f * math::Vector::dot_product(self.v, q);
-        if t > std::f32::EPSILON {
-            let result = ray.origin + ray.direction * t;
-            return true;
-        } else {
-            return false;
-        }
+        self.get_point_intersected_by(ray).is_some()
     }
 
     fn get_point_intersected_by(&self, ray: &util::Ray) -> Option<math::Vector> {
         // Implements the Möller–Trumbore intersection algorithm
         let h = math::Vector::cross_product(ray.direction, self.v);
         let a = math::Vector::dot_product(self.u, h);
-        if a > -std::f32::EPSILON && a < std::f32::EPSILON {
+        // Check if the ray and the triangle are parallel
+        if a > -f32::EPSILON && a < f32::EPSILON {
             return None;
         }
         let f = 1.0 / a;
-        let s = (ray.origin - self.p0);
+        let s = ray.origin - self.p0;
         let u = f * (math::Vector::dot_product(s, h));
-        // Check if the ray and the triangle are parallel
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
         let q = math::Vector::cross_product(s, self.u);
@@ -251,7 +306,7 @@ impl Object for Triangle {
             return None;
         }
         let t = f * math::Vector::dot_product(self.v, q);
-        if t > std::f32::EPSILON {
+        if t > f32::EPSILON {
             let result = ray.origin + ray.direction * t;
             return Some(result);
         } else {
@@ -259,26 +314,83 @@ impl Object for Triangle {
         }
     }
 
-    fn compute_color(
-        &self,
-        point: &math::Vector,
-        ray: &util::Ray,
-        light: &light::Light,
-    ) -> util::Color {
-        let v = -ray.direction;
-        let half_vector = ((light.position - *point).normalize() + v).normalize();
-        let specular = self.surface.specular_constant
-            * light.intensity
-            * math::Vector::dot_product(self.normal, half_vector)
-                .powf(50.0)
-                .max(0.0);
-
-        let diffuse = self.surface.diffuse_constant
-            * light.intensity
-            * math::Vector::dot_product(self.normal, (light.position - *point).normalize())
-                .max(0.0);
-        let illumination = diffuse + specular;
-        let color = util::Color::new(illumination.x, illumination.y, illumination.z);
-        return color;
+    fn get_normal_at(&self, _point: &math::Vector, _time: f32) -> math::Vector {
+        self.normal
+    }
+
+    fn get_surface(&self) -> &Surface {
+        &self.surface
+    }
+}
+
+/*
+Wraps an object to move it over time. Rays are brought into the space of the wrapped object at their
+own time, so each sample of the shutter interval sees the object at a different place.
+*/
+pub struct MovingObject {
+    object: Box<dyn Object>,
+    motion: motion::Motion,
+}
+
+impl MovingObject {
+    pub fn new(object: Box<dyn Object>, motion: motion::Motion) -> MovingObject {
+        MovingObject { object, motion }
+    }
+}
+
+impl Object for MovingObject {
+    fn is_intersected_by(&self, ray: &util::Ray) -> bool {
+        self.get_point_intersected_by(ray).is_some()
+    }
+
+    fn get_point_intersected_by(&self, ray: &util::Ray) -> Option<math::Vector> {
+        let keyframe = self.motion.get_keyframe_at(ray.time);
+        let local_ray = util::Ray::new(
+            keyframe.inverse_transform_point(ray.origin),
+            keyframe.inverse_transform_vector(ray.direction),
+        )
+        .with_time(ray.time);
+        self.object
+            .get_point_intersected_by(&local_ray)
+            .map(|point| keyframe.transform_point(point))
+    }
+
+    fn get_normal_at(&self, point: &math::Vector, time: f32) -> math::Vector {
+        let keyframe = self.motion.get_keyframe_at(time);
+        let local_point = keyframe.inverse_transform_point(*point);
+        keyframe.transform_normal(self.object.get_normal_at(&local_point, time))
+    }
+
+    fn get_surface(&self) -> &Surface {
+        self.object.get_surface()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_sphere_is_hit_where_it_is_at_the_ray_time_test() {
+        let surface = Surface::new(
+            math::Vector::new(1.0, 1.0, 1.0),
+            math::Vector::new(0.0, 0.0, 0.0),
+        );
+        let sphere = Sphere::new(math::Vector::new(0.0, 0.0, 5.0), 1.0, surface);
+        let moving_sphere = MovingObject::new(
+            Box::new(sphere),
+            motion::Motion::Linear(math::Vector::new(3.0, 0.0, 0.0)),
+        );
+        let ray = util::Ray::new(
+            math::Vector::new(3.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+        );
+        assert!(!moving_sphere.is_intersected_by(&ray));
+        let point = moving_sphere
+            .get_point_intersected_by(&ray.with_time(1.0))
+            .unwrap();
+        assert!((point - math::Vector::new(3.0, 0.0, 4.0)).magnitude() < 1e-5);
+        let normal = moving_sphere.get_normal_at(&point, 1.0);
+        assert!((normal - math::Vector::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
     }
 }
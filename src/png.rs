@@ -0,0 +1,209 @@
+use super::{deflate, image};
+use std::io;
+use std::io::prelude::*;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+/*
+Writes the image as a PNG file, in RGB or RGBA depending on whether it has an alpha channel. The
+values are clamped to [0, 1] and quantized without any transform, so a display transform must have
+been applied before for the file to look right.
+*/
+pub fn write<W: Write>(
+    image: &image::Image,
+    writer: &mut W,
+    bit_depth: BitDepth,
+) -> io::Result<()> {
+    let (bytes_per_sample, max_value) = match bit_depth {
+        BitDepth::Eight => (1, 255),
+        BitDepth::Sixteen => (2, 65535),
+    };
+    let channels = image.get_channels();
+    let bytes_per_pixel = channels * bytes_per_sample;
+    let row_length = image.get_width() * bytes_per_pixel;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.get_width() as u32).to_be_bytes());
+    header.extend_from_slice(&(image.get_height() as u32).to_be_bytes());
+    header.push((bytes_per_sample * 8) as u8);
+    // Color type 2 is RGB and 6 is RGBA
+    header.push(if image.has_alpha() { 6 } else { 2 });
+    // Deflate compression, adaptive filtering and no interlacing
+    header.extend_from_slice(&[0, 0, 0]);
+
+    let mut filtered = Vec::with_capacity((row_length + 1) * image.get_height());
+    let mut previous_row = vec![0; row_length];
+    let mut row = Vec::with_capacity(row_length);
+    for (i, pixels) in image
+        .get_data()
+        .chunks(image.get_width() * channels)
+        .enumerate()
+    {
+        row.clear();
+        for &value in pixels {
+            let code = image::quantize(value, max_value);
+            match bit_depth {
+                BitDepth::Eight => row.push(code as u8),
+                BitDepth::Sixteen => row.extend_from_slice(&code.to_be_bytes()),
+            }
+        }
+        let previous = if i == 0 {
+            &previous_row[..0]
+        } else {
+            &previous_row[..]
+        };
+        filter_row(&row, previous, bytes_per_pixel, &mut filtered);
+        previous_row.copy_from_slice(&row);
+    }
+
+    writer.write_all(&SIGNATURE)?;
+    write_chunk(writer, b"IHDR", &header)?;
+    write_chunk(writer, b"IDAT", &deflate::compress_zlib(&filtered))?;
+    write_chunk(writer, b"IEND", &[])?;
+    Ok(())
+}
+
+/*
+Appends the row, preceded by its filter type, after filtering it with the filter giving the smallest
+sum of absolute values, the heuristic recommended by the specification. `previous` is empty for the
+first row.
+*/
+fn filter_row(row: &[u8], previous: &[u8], bytes_per_pixel: usize, output: &mut Vec<u8>) {
+    let above = |k: usize| if previous.is_empty() { 0 } else { previous[k] };
+    let left = |k: usize| {
+        if k >= bytes_per_pixel {
+            row[k - bytes_per_pixel]
+        } else {
+            0
+        }
+    };
+    let upper_left = |k: usize| {
+        if k >= bytes_per_pixel && !previous.is_empty() {
+            previous[k - bytes_per_pixel]
+        } else {
+            0
+        }
+    };
+    let filter = |filter_type: u8, k: usize| -> u8 {
+        let predictor = match filter_type {
+            0 => 0,
+            1 => left(k),
+            2 => above(k),
+            3 => ((left(k) as u16 + above(k) as u16) / 2) as u8,
+            _ => paeth_predictor(left(k), above(k), upper_left(k)),
+        };
+        row[k].wrapping_sub(predictor)
+    };
+
+    let mut best_type = 0;
+    let mut best_cost = u64::MAX;
+    for filter_type in 0..5 {
+        let cost: u64 = (0..row.len())
+            .map(|k| (filter(filter_type, k) as i8).unsigned_abs() as u64)
+            .sum();
+        if cost < best_cost {
+            best_cost = cost;
+            best_type = filter_type;
+        }
+    }
+    output.push(best_type);
+    output.extend((0..row.len()).map(|k| filter(best_type, k)));
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(chunk_type)?;
+    writer.write_all(data)?;
+    let mut crc = Crc32::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    writer.write_all(&crc.finish().to_be_bytes())
+}
+
+// CRC-32 with the polynomial of ISO 3309, computed a byte at a time from a table
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+        Crc32 {
+            table,
+            value: 0xffff_ffff,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.value =
+                self.table[((self.value ^ byte as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    fn crc32_test() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn png_chunks_test() {
+        let mut image = image::Image::new(1, 2).with_alpha();
+        image.set_pixel(0, 1, util::Color::new(1.0, 0.5, 0.0));
+        let mut bytes = Vec::new();
+        write(&image, &mut bytes, BitDepth::Sixteen).unwrap();
+        assert_eq!(bytes[..8], SIGNATURE);
+        // IHDR: width 2, height 1, 16 bits, RGBA
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[16..29], &[0, 0, 0, 2, 0, 0, 0, 1, 16, 6, 0, 0, 0]);
+        assert_eq!(&bytes[29..33], &[0xa4, 0xb2, 0xa3, 0xc9]);
+        assert_eq!(
+            &bytes[bytes.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use super::{image, util};
+use std::io;
+use std::io::prelude::*;
+
+/*
+Netpbm color images, in ASCII (P3) or binary (P6) form, and the portable float map (PF) which
+stores the linear values themselves. The values of PPM files are read as they are, in [0, 1],
+without undoing any transform they were encoded with.
+*/
+
+pub fn write_ascii_ppm<W: Write>(image: &image::Image, writer: &mut W) -> io::Result<()> {
+    let max_value: u16 = 255;
+    writer.write_fmt(format_args!(
+        "P3\n{} {}\n{}\n",
+        image.get_width(),
+        image.get_height(),
+        max_value
+    ))?;
+    for i in 0..image.get_height() {
+        for j in 0..image.get_width() {
+            let pixel = image.get_pixel(i, j);
+            writer.write_fmt(format_args!(
+                "{} {} {}\n",
+                image::quantize(pixel.r, max_value),
+                image::quantize(pixel.g, max_value),
+                image::quantize(pixel.b, max_value)
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_binary_ppm<W: Write>(image: &image::Image, writer: &mut W) -> io::Result<()> {
+    let max_value: u16 = 255;
+    writer.write_fmt(format_args!(
+        "P6\n{} {}\n{}\n",
+        image.get_width(),
+        image.get_height(),
+        max_value
+    ))?;
+    let mut row = Vec::with_capacity(image.get_width() * 3);
+    for i in 0..image.get_height() {
+        row.clear();
+        for j in 0..image.get_width() {
+            let pixel = image.get_pixel(i, j);
+            for &value in [pixel.r, pixel.g, pixel.b].iter() {
+                row.push(image::quantize(value, max_value) as u8);
+            }
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+// Writes a little-endian float map, whose rows go from the bottom to the top of the image
+pub fn write_pfm<W: Write>(image: &image::Image, writer: &mut W) -> io::Result<()> {
+    // A negative scale marks little-endian values
+    writer.write_fmt(format_args!(
+        "PF\n{} {}\n-1.0\n",
+        image.get_width(),
+        image.get_height()
+    ))?;
+    let mut row = Vec::with_capacity(image.get_width() * 12);
+    for i in (0..image.get_height()).rev() {
+        row.clear();
+        for j in 0..image.get_width() {
+            let pixel = image.get_pixel(i, j);
+            for &value in [pixel.r, pixel.g, pixel.b].iter() {
+                row.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+// Reads a P3 or P6 pixmap, or a color (PF) or grayscale (Pf) float map
+pub fn read<R: Read>(reader: &mut R) -> io::Result<image::Image> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let mut header = HeaderReader {
+        bytes: &bytes,
+        position: 0,
+    };
+    let magic_number = header.next_token()?;
+    let width = header.next_number::<usize>()?;
+    let height = header.next_number::<usize>()?;
+    // The dimensions come from the file, so they are checked against its data before allocating
+    let value_count = height
+        .checked_mul(width)
+        .and_then(|pixel_count| pixel_count.checked_mul(3))
+        .ok_or_else(|| invalid_data("the image is too large"))?;
+    let image = match magic_number.as_str() {
+        "P3" => {
+            let max_value = get_max_value(&mut header)?;
+            // Every value takes at least one digit
+            header.get_data(value_count)?;
+            let mut image = image::Image::new(height, width);
+            for i in 0..height {
+                for j in 0..width {
+                    let r = header.next_number::<u16>()? as f32 / max_value;
+                    let g = header.next_number::<u16>()? as f32 / max_value;
+                    let b = header.next_number::<u16>()? as f32 / max_value;
+                    image.set_pixel(i, j, util::Color::new(r, g, b));
+                }
+            }
+            image
+        }
+        "P6" => {
+            let max_value = get_max_value(&mut header)?;
+            // Values take two bytes, most significant first, when they don't fit in one
+            let bytes_per_value = if max_value > 255.0 { 2 } else { 1 };
+            let data = header.get_data(get_length(value_count, bytes_per_value)?)?;
+            let mut image = image::Image::new(height, width);
+            let mut values = data.chunks(bytes_per_value).map(|value| {
+                value
+                    .iter()
+                    .fold(0_u32, |code, &byte| code << 8 | byte as u32) as f32
+                    / max_value
+            });
+            for i in 0..height {
+                for j in 0..width {
+                    let mut next = || values.next().unwrap_or(0.0);
+                    image.set_pixel(i, j, util::Color::new(next(), next(), next()));
+                }
+            }
+            image
+        }
+        "PF" | "Pf" => {
+            let channels = if magic_number == "PF" { 3 } else { 1 };
+            let scale = header.next_number::<f32>()?;
+            let data = header.get_data(get_length(value_count / 3 * channels, 4)?)?;
+            let mut image = image::Image::new(height, width);
+            let mut values = data.chunks(4).map(|value| {
+                let value = [value[0], value[1], value[2], value[3]];
+                if scale < 0.0 {
+                    f32::from_le_bytes(value)
+                } else {
+                    f32::from_be_bytes(value)
+                }
+            });
+            for i in (0..height).rev() {
+                for j in 0..width {
+                    let mut next = || values.next().unwrap_or(0.0);
+                    let color = if channels == 3 {
+                        util::Color::new(next(), next(), next())
+                    } else {
+                        let value = next();
+                        util::Color::new(value, value, value)
+                    };
+                    image.set_pixel(i, j, color);
+                }
+            }
+            image
+        }
+        _ => {
+            return Err(invalid_data(&format!(
+                "unsupported magic number '{}'",
+                magic_number
+            )))
+        }
+    };
+    Ok(image)
+}
+
+fn get_length(value_count: usize, bytes_per_value: usize) -> io::Result<usize> {
+    value_count
+        .checked_mul(bytes_per_value)
+        .ok_or_else(|| invalid_data("the image is too large"))
+}
+
+fn get_max_value(header: &mut HeaderReader) -> io::Result<f32> {
+    let max_value = header.next_number::<u16>()?;
+    if max_value == 0 {
+        return Err(invalid_data("the maximum value must be positive"));
+    }
+    Ok(max_value as f32)
+}
+
+// Reads the whitespace-separated fields of the header, skipping comments
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    fn next_token(&mut self) -> io::Result<String> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err(invalid_data("unexpected end of file")),
+            }
+        }
+        let start = self.position;
+        while self.position < self.bytes.len() && !self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    fn next_number<T: std::str::FromStr>(&mut self) -> io::Result<T> {
+        let token = self.next_token()?;
+        token
+            .parse::<T>()
+            .map_err(|_| invalid_data(&format!("invalid number '{}'", token)))
+    }
+
+    // Binary data, which starts after the single whitespace ending the header
+    fn get_data(&self, length: usize) -> io::Result<&'a [u8]> {
+        let start = self.position + 1;
+        if start > self.bytes.len() || length > self.bytes.len() - start {
+            return Err(invalid_data("unexpected end of file"));
+        }
+        Ok(&self.bytes[start..(start + length)])
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PNM file: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_image() -> image::Image {
+        let mut image = image::Image::new(2, 3);
+        for i in 0..2 {
+            for j in 0..3 {
+                let value = (i * 3 + j) as f32;
+                image.set_pixel(i, j, util::Color::new(value / 5.0, 1.0, value * 10.0));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn binary_ppm_round_trip_test() {
+        let image = get_test_image();
+        let mut bytes = Vec::new();
+        write_binary_ppm(&image, &mut bytes).unwrap();
+        assert_eq!(&bytes[..11], b"P6\n3 2\n255\n");
+        let read = read(&mut &bytes[..]).unwrap();
+        assert_eq!(
+            read.get_pixel(1, 0),
+            util::Color::new(153.0 / 255.0, 1.0, 1.0)
+        );
+        assert_eq!(read.get_pixel(0, 0), util::Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn pfm_round_trip_test() {
+        let image = get_test_image();
+        let mut bytes = Vec::new();
+        write_pfm(&image, &mut bytes).unwrap();
+        let read = read(&mut &bytes[..]).unwrap();
+        assert_eq!(read.get_data(), image.get_data());
+    }
+
+    #[test]
+    fn ascii_ppm_with_comments_test() {
+        let contents = b"P3\n# Written by hand\n2 1\n# Maximum value\n15\n15 0 0  3 6 9\n";
+        let read = read(&mut &contents[..]).unwrap();
+        assert_eq!(read.get_pixel(0, 0), util::Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.get_pixel(0, 1), util::Color::new(0.2, 0.4, 0.6));
+    }
+
+    #[test]
+    fn reject_dimensions_larger_than_the_data_test() {
+        for contents in [
+            &b"P6\n100000 100000\n255\n\0\0\0"[..],
+            &b"PF\n18446744073709551615 2\n-1.0\n\0\0\0\0"[..],
+            &b"P3\n100000 100000\n255\n0 0 0\n"[..],
+        ]
+        .iter()
+        {
+            assert_eq!(
+                read(&mut &contents[..]).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// State of a render, reported after every tile it completes
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Progress {
+    // Tiles completed in the current pass over the image
+    pub completed_tiles: usize,
+    pub total_tiles: usize,
+    // Passes completed over the whole image
+    pub passes: u32,
+    // Estimated fraction of the render done, in [0, 1]
+    pub fraction: f32,
+    pub elapsed: Duration,
+    // Estimated time left, unknown until part of the render is done
+    pub remaining: Option<Duration>,
+    pub rays_per_second: f32,
+}
+
+// Sendable, so that a renderer can report its progress while it runs on another thread
+pub type ProgressCallback = Box<dyn Fn(&Progress) + Send>;
+
+impl Progress {
+    pub fn get_percentage(&self) -> f32 {
+        self.fraction * 100.0
+    }
+}
+
+/*
+Flag asking a render to stop, which can be shared with other threads, like the one handling the
+user interface. The render checks it before every tile, and returns the image of the samples it
+took until then.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -1,43 +1,784 @@
-use super::{camera, image, scene, util};
-use rand::prelude::*;
+use super::{adaptive, aov, camera, film, filter, image, progress, sampler, scene, tonemap, util};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 const ITERATIONS_PER_PIXEL: u32 = 100;
 
+const STATE_MAGIC_NUMBER: &[u8; 8] = b"LPSTATE1";
+
+// Side of the square tiles passes go through the image in, reporting progress after each one
+const TILE_SIZE: usize = 16;
+
+// Condition ending a progressive render, checked after every pass over the image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StopCondition {
+    // Wall-clock time spent rendering, including the time before the render was resumed
+    Time(Duration),
+    // Number of passes, each taking one sample in every pixel
+    Samples(u32),
+    // Relative error of the pixels, on average, as estimated by adaptive::PixelStatistics
+    Error(f32),
+}
+
+impl StopCondition {
+    fn is_met(&self, accumulation: &Accumulation) -> bool {
+        match *self {
+            StopCondition::Time(duration) => accumulation.elapsed >= duration,
+            StopCondition::Samples(samples) => accumulation.passes >= samples,
+            StopCondition::Error(error) => accumulation.get_error() <= error,
+        }
+    }
+}
+
+// Start of a call to render, against which its progress is measured
+struct Session {
+    stop_condition: StopCondition,
+    start: Instant,
+    ray_count: u64,
+}
+
+/*
+Samples accumulated by a render so far. Along with the seed, they're all a render needs to go on from
+where it stopped: the sampler gives the same numbers to the next samples of every pixel whether or
+not the render was interrupted.
+*/
+struct Accumulation {
+    film: film::Film,
+    aov_films: Vec<(aov::Aov, film::Film)>,
+    statistics: Vec<adaptive::PixelStatistics>,
+    passes: u32,
+    elapsed: Duration,
+}
+
+impl Accumulation {
+    fn write<W: Write>(&self, writer: &mut W, seed: u32) -> io::Result<()> {
+        writer.write_all(STATE_MAGIC_NUMBER)?;
+        writer.write_all(&seed.to_le_bytes())?;
+        writer.write_all(&self.passes.to_le_bytes())?;
+        writer.write_all(&self.elapsed.as_secs_f64().to_le_bytes())?;
+        self.film.write_state(writer)?;
+        for statistics in self.statistics.iter() {
+            writer.write_all(&statistics.to_bytes())?;
+        }
+        writer.write_all(&(self.aov_films.len() as u32).to_le_bytes())?;
+        for (aov, film) in self.aov_films.iter() {
+            writer.write_all(&(aov.get_name().len() as u32).to_le_bytes())?;
+            writer.write_all(aov.get_name().as_bytes())?;
+            film.write_state(writer)?;
+        }
+        Ok(())
+    }
+
+    // Reads the state saved by `write` into an accumulation started for the same render
+    fn read<R: Read>(&mut self, reader: &mut R, seed: u32) -> io::Result<()> {
+        let mut magic_number = [0; 8];
+        reader.read_exact(&mut magic_number)?;
+        if &magic_number != STATE_MAGIC_NUMBER {
+            return Err(invalid_data("not a render state file"));
+        }
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        if u32::from_le_bytes(bytes) != seed {
+            return Err(invalid_data("the render was saved with another seed"));
+        }
+        reader.read_exact(&mut bytes)?;
+        self.passes = u32::from_le_bytes(bytes);
+        let mut elapsed = [0; 8];
+        reader.read_exact(&mut elapsed)?;
+        self.elapsed = Duration::try_from_secs_f64(f64::from_le_bytes(elapsed))
+            .map_err(|_| invalid_data("invalid elapsed time"))?;
+        self.film.read_state(reader)?;
+        for statistics in self.statistics.iter_mut() {
+            let mut bytes = [0; 12];
+            reader.read_exact(&mut bytes)?;
+            *statistics = adaptive::PixelStatistics::from_bytes(bytes);
+        }
+        reader.read_exact(&mut bytes)?;
+        if u32::from_le_bytes(bytes) as usize != self.aov_films.len() {
+            return Err(invalid_data("the render was saved with other AOVs"));
+        }
+        for (aov, film) in self.aov_films.iter_mut() {
+            reader.read_exact(&mut bytes)?;
+            // The length comes from the file, so it's checked before allocating the name
+            if u32::from_le_bytes(bytes) as usize != aov.get_name().len() {
+                return Err(invalid_data("the render was saved with other AOVs"));
+            }
+            let mut name = vec![0; aov.get_name().len()];
+            reader.read_exact(&mut name)?;
+            if name != aov.get_name().as_bytes() {
+                return Err(invalid_data("the render was saved with other AOVs"));
+            }
+            film.read_state(reader)?;
+        }
+        Ok(())
+    }
+
+    fn get_error(&self) -> f32 {
+        let total: f32 = self
+            .statistics
+            .iter()
+            .map(|statistics| statistics.get_relative_error())
+            .sum();
+        total / self.statistics.len() as f32
+    }
+}
+
 pub struct Renderer {
-    camera: camera::Camera,
+    camera: Box<dyn camera::Camera>,
     scene: scene::Scene,
+    filter: filter::Filter,
+    sampler: Box<dyn sampler::Sampler>,
+    seed: u32,
+    adaptive_sampling: Option<adaptive::AdaptiveSampling>,
+    display_transform: Option<tonemap::DisplayTransform>,
+    checkpoints: Option<(String, Duration)>,
+    state_file: Option<(String, Duration)>,
+    progress_callback: Option<progress::ProgressCallback>,
+    cancellation_token: Option<progress::CancellationToken>,
+    aovs: Vec<aov::Aov>,
 }
 
 impl Renderer {
-    pub fn new(c: camera::Camera, s: scene::Scene) -> Renderer {
+    pub fn new(c: Box<dyn camera::Camera>, s: scene::Scene) -> Renderer {
         Renderer {
             camera: c,
             scene: s,
+            filter: filter::Filter::Box { radius: 0.5 },
+            sampler: Box::new(sampler::RandomSampler::new(ITERATIONS_PER_PIXEL)),
+            seed: 0,
+            adaptive_sampling: None,
+            display_transform: None,
+            checkpoints: None,
+            state_file: None,
+            progress_callback: None,
+            cancellation_token: None,
+            aovs: Vec::new(),
         }
     }
 
+    // The sampler also sets the number of samples per pixel
+    pub fn with_sampler(mut self, sampler: Box<dyn sampler::Sampler>) -> Renderer {
+        self.sampler = sampler;
+        self
+    }
+
+    // Renders with the same seed are identical, those with different seeds have independent noise
+    pub fn with_seed(mut self, seed: u32) -> Renderer {
+        self.seed = seed;
+        self
+    }
+
+    // The number of samples per pixel of the sampler becomes the maximum a pixel can take
+    pub fn with_adaptive_sampling(
+        mut self,
+        adaptive_sampling: adaptive::AdaptiveSampling,
+    ) -> Renderer {
+        self.adaptive_sampling = Some(adaptive_sampling);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: filter::Filter) -> Renderer {
+        self.filter = filter;
+        self
+    }
+
+    // Renders display-ready images instead of linear radiance
+    pub fn with_display_transform(mut self, transform: tonemap::DisplayTransform) -> Renderer {
+        self.display_transform = Some(transform);
+        self
+    }
+
+    /*
+    Writes the image rendered so far to the file at every interval of a progressive render, in the
+    format given by the extension of the file, so that it can be looked at or kept if the render
+    is stopped early.
+    */
+    pub fn with_checkpoints(mut self, filepath: &str, interval: Duration) -> Renderer {
+        self.checkpoints = Some((filepath.to_string(), interval));
+        self
+    }
+
+    /*
+    Saves the state of progressive renders to the file at every interval and when they end, and
+    resumes them from it when it exists, so that a render stopped by a crash or a restart can be
+    run again without losing more than an interval of samples. A resumed render gives the same image
+    as an uninterrupted one, as long as the scene and the settings of the renderer didn't change;
+    only the size of the image and the seed are checked.
+    */
+    pub fn with_state_file(mut self, filepath: &str, interval: Duration) -> Renderer {
+        self.state_file = Some((filepath.to_string(), interval));
+        self
+    }
+
+    // Called after every tile, from the thread rendering
+    pub fn with_progress_callback(mut self, callback: progress::ProgressCallback) -> Renderer {
+        self.progress_callback = Some(callback);
+        self
+    }
+
+    /*
+    Sends the progress after every tile, for a user interface to receive on its own thread. The
+    render goes on when the receiver is dropped, and the channel closes when the renderer is.
+    */
+    pub fn with_progress_channel(self, sender: mpsc::Sender<progress::Progress>) -> Renderer {
+        self.with_progress_callback(Box::new(move |progress: &progress::Progress| {
+            let _ = sender.send(*progress);
+        }))
+    }
+
+    /*
+    Lets the render be stopped from elsewhere, after which it returns the image of the samples taken
+    so far. A cancelled progressive render saves its state as well, and resuming it gives the same
+    image as a render that was never cancelled.
+    */
+    pub fn with_cancellation_token(mut self, token: progress::CancellationToken) -> Renderer {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    // Buffers rendered along with the image by `render_with_aovs`, in the order they're given in
+    pub fn with_aovs(mut self, aovs: &[aov::Aov]) -> Renderer {
+        self.aovs = aovs.to_vec();
+        self
+    }
+
     pub fn render(&self) -> image::Image {
-        let mut rng = rand::thread_rng();
-        let mut image = image::Image::new(720, 1280);
-
-        for i in 0..image.get_height() {
-            for j in 0..image.get_width() {
-                let mut sum_pixel_color = util::Color::new(0.0, 0.0, 0.0);
-                for _k in 0..ITERATIONS_PER_PIXEL {
-                    let ray = self.camera.generate_ray(
-                        i as f32 + rng.gen_range(-0.5, 0.5),
-                        j as f32 + rng.gen_range(-0.5, 0.5),
-                    );
-                    sum_pixel_color += self.scene.compute_color(&ray);
+        self.render_with_heatmap().0
+    }
+
+    // Renders the image along with the heatmap of the number of samples taken by every pixel
+    pub fn render_with_heatmap(&self) -> (image::Image, image::Image) {
+        let accumulation = self.render_samples();
+        let samples_per_pixel = self.sampler.get_samples_per_pixel();
+        let sample_counts: Vec<u32> = accumulation
+            .statistics
+            .iter()
+            .map(|statistics| statistics.get_sample_count())
+            .collect();
+        let heatmap = adaptive::get_heatmap(
+            &sample_counts,
+            accumulation.film.get_height(),
+            accumulation.film.get_width(),
+            samples_per_pixel,
+        );
+        (self.get_image(&accumulation), heatmap)
+    }
+
+    // Renders the image along with its AOVs, which are left linear by the display transform
+    pub fn render_with_aovs(&self) -> (image::Image, Vec<(aov::Aov, image::Image)>) {
+        let accumulation = self.render_samples();
+        (
+            self.get_image(&accumulation),
+            self.get_aov_images(&accumulation),
+        )
+    }
+
+    /*
+    Renders passes over the whole image, each adding a sample to every pixel, until the stop
+    condition is met, ignoring the number of samples per pixel of the sampler. With adaptive
+    sampling, converged pixels are left out of the passes, and the render also stops when all of
+    them are.
+    */
+    pub fn render_progressive(&self, stop_condition: StopCondition) -> io::Result<image::Image> {
+        let accumulation = self.render_progressive_samples(stop_condition)?;
+        Ok(self.get_image(&accumulation))
+    }
+
+    pub fn render_progressive_with_aovs(
+        &self,
+        stop_condition: StopCondition,
+    ) -> io::Result<(image::Image, Vec<(aov::Aov, image::Image)>)> {
+        let accumulation = self.render_progressive_samples(stop_condition)?;
+        Ok((
+            self.get_image(&accumulation),
+            self.get_aov_images(&accumulation),
+        ))
+    }
+
+    // Takes the number of samples per pixel of the sampler
+    fn render_samples(&self) -> Accumulation {
+        let mut sampler = self.get_sampler();
+        let mut accumulation = self.start_accumulation();
+        let samples_per_pixel = sampler.get_samples_per_pixel();
+        let session = self.start_session(StopCondition::Samples(samples_per_pixel));
+        while !session.stop_condition.is_met(&accumulation) {
+            // Adaptive sampling may have every pixel converge before the last pass
+            match self.render_pass(&mut accumulation, sampler.as_mut(), &session) {
+                Some(0) | None => break,
+                Some(_) => {}
+            }
+        }
+        accumulation
+    }
+
+    fn render_progressive_samples(
+        &self,
+        stop_condition: StopCondition,
+    ) -> io::Result<Accumulation> {
+        let mut sampler = self.get_sampler();
+        let mut accumulation = self.start_accumulation();
+        if let Some((filepath, _)) = &self.state_file {
+            if Path::new(filepath).exists() {
+                let mut reader = BufReader::new(File::open(filepath)?);
+                accumulation.read(&mut reader, self.seed)?;
+            }
+        }
+        let session = self.start_session(stop_condition);
+        let mut last_checkpoint = Instant::now();
+        let mut last_state_save = Instant::now();
+        while !stop_condition.is_met(&accumulation) {
+            match self.render_pass(&mut accumulation, sampler.as_mut(), &session) {
+                Some(0) | None => break,
+                Some(_) => {}
+            }
+            if let Some((filepath, interval)) = &self.checkpoints {
+                if last_checkpoint.elapsed() >= *interval {
+                    self.get_image(&accumulation).write(filepath)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+            if let Some((_, interval)) = &self.state_file {
+                if last_state_save.elapsed() >= *interval {
+                    self.save_state(&accumulation)?;
+                    last_state_save = Instant::now();
+                }
+            }
+        }
+        self.save_state(&accumulation)?;
+        Ok(accumulation)
+    }
+
+    fn save_state(&self, accumulation: &Accumulation) -> io::Result<()> {
+        if let Some((filepath, _)) = &self.state_file {
+            // Written next to the previous state and then moved over it, so that a crash while
+            // writing can't lose both
+            let temporary_filepath = format!("{}.tmp", filepath);
+            let mut writer = BufWriter::new(File::create(&temporary_filepath)?);
+            accumulation.write(&mut writer, self.seed)?;
+            writer.flush()?;
+            drop(writer);
+            std::fs::rename(&temporary_filepath, filepath)?;
+        }
+        Ok(())
+    }
+
+    fn get_sampler(&self) -> Box<dyn sampler::Sampler> {
+        let mut sampler = self.sampler.clone_box();
+        sampler.set_seed(self.seed);
+        sampler
+    }
+
+    fn start_session(&self, stop_condition: StopCondition) -> Session {
+        Session {
+            stop_condition,
+            start: Instant::now(),
+            ray_count: self.scene.get_ray_count(),
+        }
+    }
+
+    fn start_accumulation(&self) -> Accumulation {
+        let resolution = self.camera.get_resolution();
+        let (height, width) = (resolution.height as usize, resolution.width as usize);
+        Accumulation {
+            film: film::Film::new(height, width, self.filter),
+            aov_films: self
+                .aovs
+                .iter()
+                .map(|&aov| {
+                    // Only the light passes need the filter of the image to add up to it
+                    let filter = if aov.is_light() {
+                        self.filter
+                    } else {
+                        filter::Filter::Box { radius: 0.5 }
+                    };
+                    (aov, film::Film::new(height, width, filter))
+                })
+                .collect(),
+            statistics: vec![adaptive::PixelStatistics::default(); height * width],
+            passes: 0,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    /*
+    Adds a sample to every pixel that hasn't converged, going through the image tile by tile, and
+    returns how many pixels were sampled, or None if the render was cancelled during the pass. The
+    pixels sampled before the cancellation are skipped when the pass is taken again.
+    */
+    fn render_pass(
+        &self,
+        accumulation: &mut Accumulation,
+        sampler: &mut dyn sampler::Sampler,
+        session: &Session,
+    ) -> Option<usize> {
+        let pass_start = Instant::now();
+        let (height, width) = (
+            accumulation.film.get_height(),
+            accumulation.film.get_width(),
+        );
+        let tile_columns = width.div_ceil(TILE_SIZE);
+        let total_tiles = tile_columns * height.div_ceil(TILE_SIZE);
+        let error = match session.stop_condition {
+            StopCondition::Error(_) => accumulation.get_error(),
+            _ => f32::INFINITY,
+        };
+        let mut sampled_pixels = 0;
+        for tile in 0..total_tiles {
+            if let Some(token) = &self.cancellation_token {
+                if token.is_cancelled() {
+                    accumulation.elapsed += pass_start.elapsed();
+                    return None;
+                }
+            }
+            let (first_row, first_column) = (
+                tile / tile_columns * TILE_SIZE,
+                tile % tile_columns * TILE_SIZE,
+            );
+            for i in first_row..(first_row + TILE_SIZE).min(height) {
+                for j in first_column..(first_column + TILE_SIZE).min(width) {
+                    let statistics = &mut accumulation.statistics[i * width + j];
+                    if statistics.get_sample_count() > accumulation.passes {
+                        continue;
+                    }
+                    if let Some(adaptive_sampling) = &self.adaptive_sampling {
+                        if adaptive_sampling.is_converged(statistics) {
+                            continue;
+                        }
+                    }
+                    // The samples of a pixel follow each other in the sequence of the sampler
+                    let sample_index = statistics.get_sample_count();
+                    sampler.start_pixel_sample(i, j, sample_index);
+                    let (sample_i, sample_j, radiance) = self.render_sample(sampler, i, j);
+                    let color = radiance.get_color();
+                    accumulation.film.add_sample(sample_i, sample_j, color);
+                    statistics.add(color);
+                    for (aov, film) in accumulation.aov_films.iter_mut() {
+                        if !aov.is_id() || sample_index == 0 {
+                            film.add_sample(sample_i, sample_j, aov.get_value(&radiance));
+                        }
+                    }
+                    sampled_pixels += 1;
+                }
+            }
+            if let Some(callback) = &self.progress_callback {
+                let elapsed = accumulation.elapsed + pass_start.elapsed();
+                callback(&self.get_progress(
+                    accumulation,
+                    session,
+                    tile + 1,
+                    total_tiles,
+                    elapsed,
+                    error,
+                ));
+            }
+        }
+        accumulation.elapsed += pass_start.elapsed();
+        accumulation.passes += 1;
+        Some(sampled_pixels)
+    }
+
+    // Progress of a render, `error` being the error of its pixels at the start of the pass
+    fn get_progress(
+        &self,
+        accumulation: &Accumulation,
+        session: &Session,
+        completed_tiles: usize,
+        total_tiles: usize,
+        elapsed: Duration,
+        error: f32,
+    ) -> progress::Progress {
+        let passes = accumulation.passes as f32 + completed_tiles as f32 / total_tiles as f32;
+        let fraction = match session.stop_condition {
+            StopCondition::Time(duration) => elapsed.as_secs_f32() / duration.as_secs_f32(),
+            StopCondition::Samples(samples) => passes / samples as f32,
+            // The error decreases as the inverse of the square root of the number of samples
+            StopCondition::Error(target) => {
+                if accumulation.passes > 0 && error.is_finite() {
+                    passes / (accumulation.passes as f32 * (error / target).powi(2))
+                } else {
+                    0.0
                 }
-                let average_pixel_color = sum_pixel_color / ITERATIONS_PER_PIXEL;
-                image[i][j] = vec![
-                    (average_pixel_color.r * 255.0) as u8,
-                    (average_pixel_color.g * 255.0) as u8,
-                    (average_pixel_color.b * 255.0) as u8,
-                ];
             }
         }
-        return image;
+        .clamp(0.0, 1.0);
+        let remaining = if fraction > 0.0 {
+            Some(elapsed.mul_f32((1.0 - fraction) / fraction))
+        } else {
+            None
+        };
+        let ray_count = self.scene.get_ray_count() - session.ray_count;
+        progress::Progress {
+            completed_tiles,
+            total_tiles,
+            passes: accumulation.passes,
+            fraction,
+            elapsed,
+            remaining,
+            rays_per_second: ray_count as f32 / session.start.elapsed().as_secs_f32().max(1e-6),
+        }
+    }
+
+    fn get_image(&self, accumulation: &Accumulation) -> image::Image {
+        let image = accumulation.film.get_image();
+        match &self.display_transform {
+            Some(transform) => transform.apply(&image),
+            None => image,
+        }
+    }
+
+    fn get_aov_images(&self, accumulation: &Accumulation) -> Vec<(aov::Aov, image::Image)> {
+        accumulation
+            .aov_films
+            .iter()
+            .map(|(aov, film)| (*aov, film.get_image()))
+            .collect()
+    }
+
+    // Position in the image and radiance of the sample the sampler was started on
+    fn render_sample(
+        &self,
+        sampler: &mut dyn sampler::Sampler,
+        i: usize,
+        j: usize,
+    ) -> (f32, f32, scene::RadianceSample) {
+        let (shutter_open, shutter_close) = self.camera.get_shutter_interval();
+        let (u, v) = sampler.get_2d();
+        let sample_i = i as f32 + u - 0.5;
+        let sample_j = j as f32 + v - 0.5;
+        let lens_sample = sampler.get_2d();
+        let time = shutter_open + (shutter_close - shutter_open) * sampler.get_1d();
+        let mut radiance = scene::RadianceSample::new(util::Color::new(0.0, 0.0, 0.0));
+        if let Some(ray) = self.camera.generate_ray(sample_i, sample_j, lens_sample) {
+            radiance = self
+                .scene
+                .compute_radiance(&ray.with_time(time), sampler)
+                .with_light_scale(
+                    self.camera.get_vignetting(sample_i, sample_j)
+                        * self.camera.get_exposure_scale(),
+                );
+        }
+        (sample_i, sample_j, radiance)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid render state: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{light, math, object};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn get_test_renderer() -> Renderer {
+        let camera = camera::PerspectiveCamera::new(
+            math::Vector::new(0.0, 0.0, -0.4),
+            math::Vector::new(0.0, 0.0, 1.0),
+            0.6,
+            camera::Dimensions::new(0.45, 0.8),
+            camera::Dimensions::new(6.0, 8.0),
+        );
+        let surface = object::Surface::new(
+            math::Vector::new(1.0, 0.65, 0.0),
+            math::Vector::new(0.7, 0.7, 0.7),
+        );
+        let objects: Vec<Box<dyn object::Object>> = vec![Box::new(object::Sphere::new(
+            math::Vector::new(0.0, 0.0, 1.5),
+            0.4,
+            surface,
+        ))];
+        let lights: Vec<Box<dyn light::Light>> = vec![Box::new(light::PointLight::new(
+            math::Vector::new(0.8, 0.5, 0.0),
+            1.6,
+        ))];
+        let background = scene::Background::Color(util::Color::new(0.1, 0.1, 0.1));
+        Renderer::new(
+            Box::new(camera),
+            scene::Scene::new(lights, objects, background),
+        )
+        .with_sampler(Box::new(sampler::RandomSampler::new(4)))
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_on_noisy_pixels_test() {
+        let renderer = get_test_renderer()
+            .with_sampler(Box::new(sampler::RandomSampler::new(32)))
+            .with_adaptive_sampling(adaptive::AdaptiveSampling::new(4, 0.05));
+        let (_, heatmap) = renderer.render_with_heatmap();
+        // The background is constant and stops at the minimum, the lit sphere in the center doesn't
+        let corner = heatmap.get_pixel(0, 0);
+        assert!((corner.r - 12.0 / 32.0).abs() < 1e-6 && corner.g == 0.0);
+        assert!(heatmap.get_pixel(3, 4).r > corner.r);
+    }
+
+    #[test]
+    fn progressive_render_test() {
+        let renderer = get_test_renderer();
+        let progressive = renderer
+            .render_progressive(StopCondition::Samples(4))
+            .unwrap();
+        assert_eq!(progressive.get_data(), renderer.render().get_data());
+        // Stopping on the error only returns once the average pixel is that precise
+        let mut accumulation = renderer.start_accumulation();
+        let mut sampler = renderer.get_sampler();
+        while accumulation.get_error() > 0.05 {
+            let session = renderer.start_session(StopCondition::Error(0.05));
+            renderer.render_pass(&mut accumulation, sampler.as_mut(), &session);
+        }
+        let precise = renderer
+            .render_progressive(StopCondition::Error(0.05))
+            .unwrap();
+        assert_eq!(
+            precise.get_data(),
+            renderer.get_image(&accumulation).get_data()
+        );
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render_test() {
+        let uninterrupted = get_test_renderer()
+            .render_progressive(StopCondition::Samples(6))
+            .unwrap();
+        let state_filepath = std::env::temp_dir().join("lightpaths_resume_test.state");
+        let state_filepath = state_filepath.to_str().unwrap();
+        let _ = std::fs::remove_file(state_filepath);
+        let renderer = get_test_renderer().with_state_file(state_filepath, Duration::from_secs(60));
+        renderer
+            .render_progressive(StopCondition::Samples(2))
+            .unwrap();
+        let resumed = renderer
+            .render_progressive(StopCondition::Samples(6))
+            .unwrap();
+        assert_eq!(resumed.get_data(), uninterrupted.get_data());
+        // The state can't be used by a render with another seed
+        let other_seed = get_test_renderer()
+            .with_seed(1)
+            .with_state_file(state_filepath, Duration::from_secs(60))
+            .render_progressive(StopCondition::Samples(6));
+        assert_eq!(other_seed.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(state_filepath).unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_state_test() {
+        let renderer = get_test_renderer();
+        let mut bytes = Vec::new();
+        renderer.start_accumulation().write(&mut bytes, 0).unwrap();
+        // The elapsed time follows the magic number, the seed and the number of passes
+        bytes[16..24].copy_from_slice(&(-1.0_f64).to_le_bytes());
+        let error = renderer
+            .start_accumulation()
+            .read(&mut &bytes[..], 0)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let renderer = get_test_renderer().with_aovs(&[aov::Aov::Depth]);
+        let mut bytes = Vec::new();
+        renderer.start_accumulation().write(&mut bytes, 0).unwrap();
+        // The length of the name of the only AOV comes right before the name
+        let name = bytes
+            .windows(5)
+            .position(|window| window == b"depth")
+            .unwrap();
+        bytes[(name - 4)..name].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = renderer
+            .start_accumulation()
+            .read(&mut &bytes[..], 0)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn progress_and_cancellation_test() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let token = progress::CancellationToken::new();
+        let (callback_reports, callback_token) = (reports.clone(), token.clone());
+        let renderer = get_test_renderer()
+            .with_progress_callback(Box::new(move |progress: &progress::Progress| {
+                callback_reports.lock().unwrap().push(*progress);
+                if progress.passes == 1 {
+                    callback_token.cancel();
+                }
+            }))
+            .with_cancellation_token(token);
+        let image = renderer
+            .render_progressive(StopCondition::Samples(4))
+            .unwrap();
+        // The image fits in a tile, so the render is cancelled at the start of the third pass
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[0].completed_tiles, reports[0].total_tiles), (1, 1));
+        assert_eq!(reports[1].fraction, 0.5);
+        assert!(reports[1].remaining.is_some() && reports[1].rays_per_second > 0.0);
+        let two_passes = get_test_renderer()
+            .render_progressive(StopCondition::Samples(2))
+            .unwrap();
+        assert_eq!(image.get_data(), two_passes.get_data());
+    }
+
+    #[test]
+    fn progress_channel_test() {
+        let (sender, receiver) = mpsc::channel();
+        let receiving = thread::spawn(move || receiver.iter().collect::<Vec<progress::Progress>>());
+        let renderer = get_test_renderer().with_progress_channel(sender);
+        renderer
+            .render_progressive(StopCondition::Samples(2))
+            .unwrap();
+        // Dropping the renderer closes the channel, which ends the receiving thread
+        drop(renderer);
+        let reports = receiving.join().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].passes, 0);
+        assert_eq!(reports[1].fraction, 1.0);
+    }
+
+    #[test]
+    fn aovs_test() {
+        let renderer = get_test_renderer().with_aovs(&[
+            aov::Aov::Depth,
+            aov::Aov::ObjectId,
+            aov::Aov::Emission,
+            aov::Aov::DirectDiffuse,
+            aov::Aov::DirectSpecular,
+            aov::Aov::IndirectDiffuse,
+            aov::Aov::IndirectSpecular,
+        ]);
+        let (image, aovs) = renderer.render_with_aovs();
+        assert_eq!(image.get_data(), renderer.render().get_data());
+        let names: Vec<&str> = aovs.iter().map(|(aov, _)| aov.get_name()).collect();
+        assert_eq!(names[..2], ["depth", "object_id"]);
+        // The sphere fills the center of the image and leaves the corners to the background
+        assert!((aovs[0].1.get_pixel(3, 4).r - 1.5).abs() < 0.1);
+        assert_eq!(aovs[1].1.get_pixel(3, 4).r, 1.0);
+        assert_eq!(aovs[1].1.get_pixel(0, 0).r, 0.0);
+        // The light passes add up to the image
+        for (i, j) in [(0, 0), (3, 4)].iter() {
+            let sum = aovs[2..]
+                .iter()
+                .fold(util::Color::new(0.0, 0.0, 0.0), |sum, (_, aov_image)| {
+                    sum + aov_image.get_pixel(*i, *j)
+                });
+            let pixel = image.get_pixel(*i, *j);
+            assert!((sum.r - pixel.r).abs() < 1e-5 && (sum.g - pixel.g).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn seeded_renders_are_identical_test() {
+        let first = get_test_renderer().with_seed(3).render();
+        let second = get_test_renderer().with_seed(3).render();
+        assert_eq!(first.get_data(), second.get_data());
+        let other = get_test_renderer().with_seed(4).render();
+        assert_ne!(first.get_data(), other.get_data());
     }
 }
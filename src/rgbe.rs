@@ -0,0 +1,341 @@
+use super::{image, util};
+use std::io;
+use std::io::prelude::*;
+
+/*
+Radiance HDR images, where each pixel takes four bytes: a mantissa for each of the red, green and
+blue channels, sharing the exponent stored in the fourth byte. Scanlines are written with the
+run-length encoding of the Radiance tools, which encodes each of the four components separately.
+*/
+
+const MIN_RUN_LENGTH: usize = 4;
+const MAX_RUN_LENGTH: usize = 127;
+const MAX_DUMP_LENGTH: usize = 128;
+
+pub fn write<W: Write>(image: &image::Image, writer: &mut W) -> io::Result<()> {
+    let height = image.get_height();
+    let width = image.get_width();
+    writer.write_fmt(format_args!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    ))?;
+    let mut scanline = vec![[0_u8; 4]; width];
+    let mut component = Vec::with_capacity(width);
+    let mut encoded = Vec::new();
+    for i in 0..height {
+        for (j, pixel) in scanline.iter_mut().enumerate() {
+            *pixel = to_rgbe(image.get_pixel(i, j));
+        }
+        encoded.clear();
+        if !(8..=0x7fff).contains(&width) {
+            // The run-length encoding can't describe scanlines of these widths
+            for pixel in scanline.iter() {
+                encoded.extend_from_slice(pixel);
+            }
+        } else {
+            encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for k in 0..4 {
+                component.clear();
+                component.extend(scanline.iter().map(|pixel| pixel[k]));
+                encode_runs(&component, &mut encoded);
+            }
+        }
+        writer.write_all(&encoded)?;
+    }
+    Ok(())
+}
+
+// Appends the bytes as runs of a repeated byte, and dumps of bytes that don't repeat enough
+fn encode_runs(bytes: &[u8], output: &mut Vec<u8>) {
+    let mut position = 0;
+    while position < bytes.len() {
+        let mut run_start = position;
+        let mut run_length = 0;
+        while run_start < bytes.len() {
+            run_length = bytes[run_start..]
+                .iter()
+                .take(MAX_RUN_LENGTH)
+                .take_while(|&&byte| byte == bytes[run_start])
+                .count();
+            if run_length >= MIN_RUN_LENGTH {
+                break;
+            }
+            run_start += run_length;
+        }
+        while position < run_start {
+            let dump_length = (run_start - position).min(MAX_DUMP_LENGTH);
+            output.push(dump_length as u8);
+            output.extend_from_slice(&bytes[position..(position + dump_length)]);
+            position += dump_length;
+        }
+        if run_length >= MIN_RUN_LENGTH && run_start < bytes.len() {
+            output.push((128 + run_length) as u8);
+            output.push(bytes[run_start]);
+            position += run_length;
+        }
+    }
+}
+
+pub fn read<R: Read>(reader: &mut R) -> io::Result<image::Image> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let mut position = 0;
+    let mut next_line = || -> io::Result<String> {
+        let end = bytes[position..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| invalid_data("unexpected end of header"))?;
+        let line = String::from_utf8_lossy(&bytes[position..(position + end)]).into_owned();
+        position += end + 1;
+        Ok(line)
+    };
+
+    if !next_line()?.starts_with("#?") {
+        return Err(invalid_data("missing identifier"));
+    }
+    // Values were multiplied by every exposure given in the header
+    let mut exposure = 1.0;
+    loop {
+        let line = next_line()?;
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format.trim() != "32-bit_rle_rgbe" {
+                return Err(invalid_data(&format!("unsupported format '{}'", format)));
+            }
+        } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+            exposure *= value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| invalid_data("invalid exposure"))?;
+        }
+    }
+    let resolution = next_line()?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width, top_to_bottom) = match fields.as_slice() {
+        [y, height, "+X", width] if *y == "-Y" || *y == "+Y" => (
+            height
+                .parse::<usize>()
+                .map_err(|_| invalid_data("invalid height"))?,
+            width
+                .parse::<usize>()
+                .map_err(|_| invalid_data("invalid width"))?,
+            *y == "-Y",
+        ),
+        _ => {
+            return Err(invalid_data(&format!(
+                "unsupported orientation '{}'",
+                resolution
+            )))
+        }
+    };
+
+    let mut data = &bytes[position..];
+    let pixel_count = height
+        .checked_mul(width)
+        .filter(|pixel_count| pixel_count.checked_mul(3).is_some())
+        .ok_or_else(|| invalid_data("the image is too large"))?;
+    /*
+    The dimensions come from the file, so they are checked against its data before allocating. Every
+    scanline takes at least four bytes, and the runs of encoded scanlines take two bytes for at most
+    127 pixels of each of the four components, while flat pixels take four bytes each.
+    */
+    if height > data.len() / 4 || pixel_count / MAX_RUN_LENGTH > data.len() / 8 {
+        return Err(invalid_data("unexpected end of file"));
+    }
+    let mut image = image::Image::new(height, width);
+    let mut scanline = vec![[0_u8; 4]; width];
+    for row in 0..height {
+        data = decode_scanline(data, &mut scanline)?;
+        let i = if top_to_bottom { row } else { height - 1 - row };
+        for (j, pixel) in scanline.iter().enumerate() {
+            image.set_pixel(i, j, from_rgbe(*pixel) * (1.0 / exposure));
+        }
+    }
+    Ok(image)
+}
+
+// Decodes a scanline in any of the encodings of the format, returning the data that follows it
+fn decode_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> io::Result<&'a [u8]> {
+    let width = scanline.len();
+    let end_of_file = || invalid_data("unexpected end of file");
+    let mut data = data;
+    if (8..=0x7fff).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2 {
+        if ((data[2] as usize) << 8 | data[3] as usize) != width {
+            return Err(invalid_data("scanline of the wrong width"));
+        }
+        data = &data[4..];
+        for k in 0..4 {
+            let mut j = 0;
+            while j < width {
+                let count = *data.first().ok_or_else(end_of_file)? as usize;
+                if count > 128 {
+                    let count = count - 128;
+                    let value = *data.get(1).ok_or_else(end_of_file)?;
+                    if count > width - j {
+                        return Err(invalid_data("run past the end of the scanline"));
+                    }
+                    for pixel in scanline[j..(j + count)].iter_mut() {
+                        pixel[k] = value;
+                    }
+                    data = &data[2..];
+                    j += count;
+                } else {
+                    if count == 0 || count > width - j {
+                        return Err(invalid_data("invalid dump length"));
+                    }
+                    let values = data.get(1..(1 + count)).ok_or_else(end_of_file)?;
+                    for (pixel, &value) in scanline[j..(j + count)].iter_mut().zip(values) {
+                        pixel[k] = value;
+                    }
+                    data = &data[(1 + count)..];
+                    j += count;
+                }
+            }
+        }
+        return Ok(data);
+    }
+
+    // Flat pixels, where (1, 1, 1, n) repeats the previous pixel, with n shifted by 8 bits more for
+    // each repetition that directly follows another
+    let mut j = 0;
+    let mut shift = 0_u32;
+    while j < width {
+        let pixel = data.get(..4).ok_or_else(end_of_file)?;
+        data = &data[4..];
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 && j > 0 {
+            // Empty runs would let the shift grow without bound
+            let count = match (pixel[3] as usize).checked_shl(shift) {
+                Some(count) if count > 0 => count,
+                _ => return Err(invalid_data("invalid run length")),
+            };
+            if count > width - j {
+                return Err(invalid_data("run past the end of the scanline"));
+            }
+            let previous = scanline[j - 1];
+            for repeated in scanline[j..(j + count)].iter_mut() {
+                *repeated = previous;
+            }
+            j += count;
+            shift += 8;
+        } else {
+            scanline[j] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            j += 1;
+            shift = 0;
+        }
+    }
+    Ok(data)
+}
+
+fn to_rgbe(color: util::Color) -> [u8; 4] {
+    let max = color.r.max(color.g).max(color.b);
+    if max.is_nan() || max < 1e-32 || max.is_infinite() {
+        return [0, 0, 0, 0];
+    }
+    // Exponent with max = mantissa * 2^exponent and the mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    let mut scale = 2_f32.powi(-exponent);
+    if max * scale >= 1.0 {
+        exponent += 1;
+        scale *= 0.5;
+    } else if max * scale < 0.5 {
+        exponent -= 1;
+        scale *= 2.0;
+    }
+    // Mantissas are rounded, without letting the largest one reach 256
+    let scale = scale * 256.0;
+    [
+        (color.r.max(0.0) * scale).round().min(255.0) as u8,
+        (color.g.max(0.0) * scale).round().min(255.0) as u8,
+        (color.b.max(0.0) * scale).round().min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+fn from_rgbe(pixel: [u8; 4]) -> util::Color {
+    if pixel[3] == 0 {
+        return util::Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2_f32.powi(pixel[3] as i32 - (128 + 8));
+    util::Color::new(
+        pixel[0] as f32 * scale,
+        pixel[1] as f32 * scale,
+        pixel[2] as f32 * scale,
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid Radiance HDR file: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgbe_pixel_test() {
+        assert_eq!(to_rgbe(util::Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(
+            from_rgbe([128, 64, 0, 129]),
+            util::Color::new(1.0, 0.5, 0.0)
+        );
+        assert_eq!(to_rgbe(util::Color::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn run_length_encoded_round_trip_test() {
+        let mut image = image::Image::new(3, 40);
+        for i in 0..3 {
+            for j in 0..40 {
+                // Runs of identical pixels next to pixels that change every time
+                let value = if j < 20 {
+                    4.0
+                } else {
+                    (i * 40 + j) as f32 * 0.1
+                };
+                image.set_pixel(i, j, util::Color::new(value, value * 0.5, value * 0.75));
+            }
+        }
+        let mut bytes = Vec::new();
+        write(&image, &mut bytes).unwrap();
+        let read = read(&mut &bytes[..]).unwrap();
+        for (value, original) in read.get_data().iter().zip(image.get_data()) {
+            assert!((value - original).abs() <= original * 0.01);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_files_test() {
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        bytes.extend_from_slice(&[128, 64, 0, 129]);
+        for _ in 0..16 {
+            bytes.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(read(&mut &bytes[..]).is_err());
+        let huge = b"#?RADIANCE\n\n-Y 100000000 +X 100000000\n\0\0\0\0";
+        assert!(read(&mut &huge[..]).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_hdr_file_test() {
+        let mut image = image::Image::new(4, 300);
+        for j in 0..300 {
+            image.set_pixel(2, j, util::Color::new(j as f32, 1.0, 0.5));
+        }
+        let mut bytes = Vec::new();
+        write(&image, &mut bytes).unwrap();
+        assert!(read(&mut &bytes[..]).is_ok());
+        assert!(read(&mut &bytes[..(bytes.len() - 10)]).is_err());
+        for dimensions in ["-Y 1 +X 1000000000000", "-Y 1000000 +X 1000000"].iter() {
+            let mut huge = format!("#?RADIANCE\n\n{}\n", dimensions).into_bytes();
+            huge.extend_from_slice(&[2, 2, 0, 0, 255, 0, 255, 0, 255, 0, 255, 0]);
+            let error = read(&mut &huge[..]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
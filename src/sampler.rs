@@ -0,0 +1,771 @@
+use std::sync::Arc;
+
+/*
+Source of the random numbers used to render a pixel sample. Every sample of a pixel draws its
+numbers in the same order: the position in the pixel, the point on the lens, the time, and then the
+numbers used by the scene to sample lights and surfaces. Each call moves on to the next dimension,
+so that samplers based on low-discrepancy sequences can spread the samples of every dimension well.
+The numbers only depend on the seed, the pixel and the index of the sample, so a render gives the
+same result every time, whatever the order its samples are taken in.
+*/
+pub trait Sampler {
+    fn get_samples_per_pixel(&self) -> u32;
+
+    // Renders with different seeds have independent noise
+    fn set_seed(&mut self, seed: u32);
+
+    // Starts the sample with the given index of the pixel (i, j), from its first dimension
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32);
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> (f32, f32);
+
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+// Position in the sequence of numbers drawn for a pixel sample
+#[derive(Copy, Clone, Debug, Default)]
+struct SampleState {
+    seed: u32,
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, i: usize, j: usize, sample_index: u32) {
+        self.pixel_seed = hash(&[i as u32, j as u32, self.seed]);
+        self.index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_dimension(&mut self) -> u32 {
+        self.dimension += 1;
+        self.dimension - 1
+    }
+}
+
+/*
+Independent uniform random numbers, the simplest and slowest to converge. Every pixel sample draws
+them from its own stream of the generator.
+*/
+#[derive(Clone)]
+pub struct RandomSampler {
+    samples_per_pixel: u32,
+    seed: u32,
+    rng: Pcg32,
+}
+
+impl RandomSampler {
+    pub fn new(samples_per_pixel: u32) -> RandomSampler {
+        RandomSampler {
+            samples_per_pixel,
+            seed: 0,
+            rng: Pcg32::new(0, 0),
+        }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn get_samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32) {
+        let pixel_seed = hash(&[i as u32, j as u32, self.seed]);
+        self.rng = Pcg32::new(pixel_seed as u64, sample_index as u64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        to_unit_float(self.rng.next_u32())
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/*
+Jittered samples, one in each cell of a grid of strata. The strata are visited in a different random
+order in every dimension and pixel, which avoids correlations between dimensions.
+*/
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    x_strata: u32,
+    y_strata: u32,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(x_strata: u32, y_strata: u32) -> StratifiedSampler {
+        StratifiedSampler {
+            // A grid needs at least one stratum along each axis
+            x_strata: x_strata.max(1),
+            y_strata: y_strata.max(1),
+            state: SampleState::default(),
+        }
+    }
+
+    // Stratum of the current sample in the current dimension, and the seed of its jitter
+    fn get_stratum(&mut self) -> (u32, u32) {
+        let strata = self.x_strata * self.y_strata;
+        let dimension = self.state.next_dimension();
+        // Samples beyond the number of strata start another, independently shuffled, round
+        let round = self.state.index / strata;
+        let seed = hash(&[self.state.pixel_seed, dimension, round]);
+        let stratum = permute(self.state.index % strata, strata, seed);
+        (stratum, hash(&[seed, self.state.index]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_samples_per_pixel(&self) -> u32 {
+        self.x_strata * self.y_strata
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.state.seed = seed;
+    }
+
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (stratum, seed) = self.get_stratum();
+        (stratum as f32 + to_unit_float(seed)) / self.get_samples_per_pixel() as f32
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (stratum, seed) = self.get_stratum();
+        let x = (stratum % self.x_strata) as f32 + to_unit_float(seed);
+        let y = (stratum / self.x_strata) as f32 + to_unit_float(hash(&[seed]));
+        (x / self.x_strata as f32, y / self.y_strata as f32)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/*
+Halton sequence, using the radical inverse in the n-th prime base for the n-th dimension, shifted
+by a random offset for each pixel and dimension. The large bases of high dimensions correlate badly
+with few samples, so the dimensions past the table of primes use random numbers.
+*/
+#[derive(Clone)]
+pub struct HaltonSampler {
+    samples_per_pixel: u32,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(samples_per_pixel: u32) -> HaltonSampler {
+        HaltonSampler {
+            samples_per_pixel,
+            state: SampleState::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn get_samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.state.seed = seed;
+    }
+
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        let seed = hash(&[self.state.pixel_seed, dimension]);
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let value = radical_inverse(self.state.index, base) + to_unit_float(seed);
+                value - value.floor()
+            }
+            None => to_unit_float(hash(&[seed, self.state.index])),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_n = 1.0;
+    let mut reversed_digits: u64 = 0;
+    while index > 0 {
+        let next = index / base;
+        reversed_digits = reversed_digits * base as u64 + (index - next * base) as u64;
+        inverse_base_n *= inverse_base;
+        index = next;
+    }
+    ((reversed_digits as f64 * inverse_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/*
+Primitive polynomials and initial direction numbers of the Sobol sequence, from the search of Joe
+and Kuo (new-joe-kuo-6.21201), as (degree, coefficients, initial numbers) for the dimensions after
+the first one, which is the van der Corput sequence.
+*/
+const JOE_KUO_PARAMETERS: [(u32, u32, &[u32]); 15] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+];
+
+// Sobol sequence, Owen-scrambled with a hash for every seed
+#[derive(Clone)]
+struct SobolSequence {
+    directions: Arc<Vec<[u32; 32]>>,
+}
+
+impl SobolSequence {
+    fn new() -> SobolSequence {
+        let mut directions = Vec::with_capacity(JOE_KUO_PARAMETERS.len() + 1);
+        let mut first = [0; 32];
+        for (k, direction) in first.iter_mut().enumerate() {
+            *direction = 1 << (31 - k);
+        }
+        directions.push(first);
+        for &(degree, coefficients, initial) in JOE_KUO_PARAMETERS.iter() {
+            let degree = degree as usize;
+            let mut v = [0_u32; 32];
+            for k in 0..32 {
+                v[k] = if k < degree {
+                    initial[k] << (31 - k)
+                } else {
+                    let mut value = v[k - degree] ^ (v[k - degree] >> degree);
+                    for l in 1..degree {
+                        if (coefficients >> (degree - 1 - l)) & 1 == 1 {
+                            value ^= v[k - l];
+                        }
+                    }
+                    value
+                };
+            }
+            directions.push(v);
+        }
+        SobolSequence {
+            directions: Arc::new(directions),
+        }
+    }
+
+    /*
+    Value of the sample in the dimension, scrambled by `seed`. Dimensions past the table of direction
+    numbers reuse its dimensions, with the samples shuffled by a nested uniform scrambling of their
+    index so that they don't correlate with the first ones.
+    */
+    fn get_value(&self, index: u32, dimension: u32, seed: u32) -> f32 {
+        let dimensions = self.directions.len() as u32;
+        let index = if dimension < dimensions {
+            index
+        } else {
+            nested_uniform_scramble(index, hash(&[seed, dimension / dimensions]))
+        };
+        let directions = &self.directions[(dimension % dimensions) as usize];
+        let mut value = 0;
+        for (bit, direction) in directions.iter().enumerate() {
+            if (index >> bit) & 1 == 1 {
+                value ^= direction;
+            }
+        }
+        to_unit_float(nested_uniform_scramble(
+            value,
+            hash(&[seed, dimension, 0x50b0]),
+        ))
+    }
+}
+
+// Owen-scrambled Sobol sequence, with a different scrambling for every pixel
+#[derive(Clone)]
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    sequence: SobolSequence,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    // The sequence is best stratified when the number of samples is a power of two
+    pub fn new(samples_per_pixel: u32) -> SobolSampler {
+        SobolSampler {
+            samples_per_pixel,
+            sequence: SobolSequence::new(),
+            state: SampleState::default(),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.state.seed = seed;
+    }
+
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        self.sequence
+            .get_value(self.state.index, dimension, self.state.pixel_seed)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/*
+Sobol sequence scrambled the same way for every pixel, with each pixel shifting it by the value of a
+blue-noise mask (Georgiev and Fajardo, "Blue-noise dithered sampling"). Neighboring pixels get very
+different shifts, so the error left at low sample counts looks like fine blue noise instead of
+blotches, which is less visible and easier to denoise. Each dimension reads the mask at a different
+offset.
+*/
+#[derive(Clone)]
+pub struct BlueNoiseSampler {
+    samples_per_pixel: u32,
+    sequence: SobolSequence,
+    mask: Arc<Vec<f32>>,
+    pixel: (usize, usize),
+    state: SampleState,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(samples_per_pixel: u32) -> BlueNoiseSampler {
+        BlueNoiseSampler {
+            samples_per_pixel,
+            sequence: SobolSequence::new(),
+            mask: Arc::new(generate_blue_noise(BLUE_NOISE_SIZE)),
+            pixel: (0, 0),
+            state: SampleState::default(),
+        }
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn get_samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.state.seed = seed;
+    }
+
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+        self.pixel = (i, j);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        let value = self
+            .sequence
+            .get_value(self.state.index, dimension, self.state.seed);
+        let offset = hash(&[dimension, self.state.seed]) as usize;
+        let row = (self.pixel.0 + offset) % BLUE_NOISE_SIZE;
+        let column = (self.pixel.1 + (offset >> 16)) % BLUE_NOISE_SIZE;
+        let shifted = value + self.mask[row * BLUE_NOISE_SIZE + column];
+        shifted - shifted.floor()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/*
+Tileable blue-noise mask generated with Ulichney's void-and-cluster method: starting from a few
+random points evenly spread by moving them from the tightest clusters into the largest voids, the
+points are ranked by removing clusters and then by filling voids. The value of each pixel is its
+rank, so that any threshold of the mask gives evenly spread points.
+*/
+fn generate_blue_noise(size: usize) -> Vec<f32> {
+    let count = size * size;
+    let sigma: f32 = 1.5;
+    // Gaussian energy of a point felt at each toroidal offset
+    let mut kernel = vec![0.0; count];
+    for dy in 0..size {
+        for dx in 0..size {
+            let y = dy.min(size - dy) as f32;
+            let x = dx.min(size - dx) as f32;
+            kernel[dy * size + dx] = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+    let update = |energy: &mut Vec<f32>, point: usize, sign: f32| {
+        let (py, px) = (point / size, point % size);
+        for y in 0..size {
+            let dy = (y + size - py) % size;
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                energy[y * size + x] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+    let tightest_cluster = |energy: &[f32], points: &[bool]| {
+        (0..count)
+            .filter(|&k| points[k])
+            .max_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+    let largest_void = |energy: &[f32], points: &[bool]| {
+        (0..count)
+            .filter(|&k| !points[k])
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+
+    let mut points = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let initial_count = (count / 10).max(1);
+    let mut placed = 0;
+    let mut candidate = 0;
+    while placed < initial_count {
+        let point = hash(&[candidate, 0xb1_0e]) as usize % count;
+        candidate += 1;
+        if !points[point] {
+            points[point] = true;
+            update(&mut energy, point, 1.0);
+            placed += 1;
+        }
+    }
+    // Ties between energies can make the swaps cycle, so they stop after one per pixel at most
+    for _ in 0..count {
+        let cluster = tightest_cluster(&energy, &points);
+        points[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&energy, &points);
+        points[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+    let mut ranked_points = points.clone();
+    let mut ranked_energy = energy.clone();
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&ranked_energy, &ranked_points);
+        ranked_points[cluster] = false;
+        update(&mut ranked_energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+    for rank in initial_count..count {
+        let void = largest_void(&energy, &points);
+        points[void] = true;
+        update(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+    ranks
+        .iter()
+        .map(|&rank| (rank as f32 + 0.5) / count as f32)
+        .collect()
+}
+
+/*
+PCG32 generator of O'Neill (XSH RR output of a 64-bit linear congruential generator). Every odd
+increment gives a different stream, so generators seeded with different streams are independent.
+*/
+#[derive(Copy, Clone, Debug)]
+struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: stream << 1 | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state
+            .wrapping_mul(0x5851_f42d_4c95_7f2d)
+            .wrapping_add(self.increment);
+        let xor_shifted = (((state >> 18) ^ state) >> 27) as u32;
+        xor_shifted.rotate_right((state >> 59) as u32)
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// Uniform float in [0, 1) from the 24 most significant bits
+fn to_unit_float(value: u32) -> f32 {
+    (value >> 8) as f32 * (1.0 / (1 << 24) as f32)
+}
+
+// Hash of a few integers, mixing each one with the lowbias32 finalizer of Chris Wellons
+fn hash(values: &[u32]) -> u32 {
+    let mut h: u32 = 0x9e37_79b9;
+    for &value in values {
+        h ^= value
+            .wrapping_add(0x7f4a_7c15)
+            .wrapping_add(h << 6)
+            .wrapping_add(h >> 2);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x21f0_aaad);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x735a_2d97);
+        h ^= h >> 15;
+    }
+    h
+}
+
+/*
+Owen scrambling of the bits of `value`, from the most significant one, using the hash-based
+permutation of Laine and Karras with the constants of Burley ("Practical Hash-based Owen Scrambling").
+*/
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+// Element `index` of a random permutation of [0, length), from Kensler's correlated multi-jittering
+fn permute(index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    let mut i = index;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks that each of the `n` samples of the first two dimensions falls in its own cell of a
+    // grid with `x_cells` columns and `n / x_cells` rows
+    fn assert_stratified_2d(sampler: &mut dyn Sampler, n: u32, x_cells: u32) {
+        let y_cells = n / x_cells;
+        let mut cells = vec![false; n as usize];
+        for k in 0..n {
+            sampler.start_pixel_sample(3, 7, k);
+            let (x, y) = sampler.get_2d();
+            let cell = (y * y_cells as f32) as u32 * x_cells + (x * x_cells as f32) as u32;
+            assert!(!cells[cell as usize], "two samples in the cell {}", cell);
+            cells[cell as usize] = true;
+        }
+    }
+
+    #[test]
+    fn stratified_sampler_test() {
+        assert_stratified_2d(&mut StratifiedSampler::new(4, 4), 16, 4);
+        let mut sampler = StratifiedSampler::new(2, 4);
+        let mut strata = [false; 8];
+        for k in 0..8 {
+            sampler.start_pixel_sample(0, 0, k);
+            sampler.get_2d();
+            let value = sampler.get_1d();
+            strata[(value * 8.0) as usize] = true;
+        }
+        assert!(strata.iter().all(|&stratum| stratum));
+        let mut sampler = StratifiedSampler::new(0, 3);
+        assert_eq!(sampler.get_samples_per_pixel(), 3);
+        sampler.start_pixel_sample(0, 0, 0);
+        assert!(sampler.get_1d() < 1.0);
+    }
+
+    #[test]
+    fn sobol_samples_are_elementary_intervals_test() {
+        let mut sampler = SobolSampler::new(64);
+        // Every elementary interval of area 1/64 of the first dimensions holds one sample
+        assert_stratified_2d(&mut sampler, 64, 8);
+        assert_stratified_2d(&mut sampler, 64, 64);
+        assert_stratified_2d(&mut sampler, 64, 2);
+        // Later dimensions are well distributed too
+        for dimension in [3, 16, 40].iter() {
+            let mut cells = [false; 16];
+            for k in 0..16 {
+                sampler.start_pixel_sample(5, 1, k);
+                for _ in 0..*dimension {
+                    sampler.get_1d();
+                }
+                cells[(sampler.get_1d() * 16.0) as usize] = true;
+            }
+            assert!(cells.iter().all(|&cell| cell));
+        }
+    }
+
+    #[test]
+    fn halton_sampler_test() {
+        assert_eq!(radical_inverse(6, 2), 0.375);
+        assert!((radical_inverse(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+        // The offsets of the pixel keep the first 2^k samples in distinct intervals of dimension 0,
+        // and the first 3^k in distinct intervals of dimension 1
+        let mut sampler = HaltonSampler::new(9);
+        let mut halves = [false; 4];
+        let mut thirds = [false; 9];
+        for k in 0..9 {
+            sampler.start_pixel_sample(2, 2, k);
+            let (x, y) = sampler.get_2d();
+            if k < 4 {
+                halves[(x * 4.0) as usize] = true;
+            }
+            thirds[(y * 9.0) as usize] = true;
+        }
+        assert!(halves.iter().chain(thirds.iter()).all(|&interval| interval));
+    }
+
+    #[test]
+    fn seeded_samplers_are_reproducible_test() {
+        // Reference output of the PCG32 demo for the seed 42 on the stream 54
+        let mut rng = Pcg32::new(42, 54);
+        let values: Vec<u32> = (0..3).map(|_| rng.next_u32()).collect();
+        assert_eq!(values, [0xa15c_02b7, 0x7b47_f409, 0xba1d_3330]);
+
+        let draw = |sampler: &mut dyn Sampler, seed: u32| {
+            sampler.set_seed(seed);
+            sampler.start_pixel_sample(4, 9, 3);
+            (sampler.get_2d(), sampler.get_1d())
+        };
+        let samplers: Vec<Box<dyn Sampler>> = vec![
+            Box::new(RandomSampler::new(4)),
+            Box::new(StratifiedSampler::new(2, 2)),
+            Box::new(HaltonSampler::new(4)),
+            Box::new(SobolSampler::new(4)),
+            Box::new(BlueNoiseSampler::new(4)),
+        ];
+        for sampler in samplers.iter() {
+            let first = draw(sampler.clone_box().as_mut(), 7);
+            assert_eq!(first, draw(sampler.clone_box().as_mut(), 7));
+            assert_ne!(first, draw(sampler.clone_box().as_mut(), 8));
+        }
+    }
+
+    #[test]
+    fn blue_noise_sampler_test() {
+        // The mask rotates the samples of each pixel, which stay less than two strata apart
+        let mut sampler = BlueNoiseSampler::new(16);
+        for dimension in 0..4 {
+            let mut values: Vec<f32> = (0..16)
+                .map(|k| {
+                    sampler.start_pixel_sample(5, 9, k);
+                    for _ in 0..dimension {
+                        sampler.get_1d();
+                    }
+                    sampler.get_1d()
+                })
+                .collect();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let wrapped_gap = values[0] + 1.0 - values[15];
+            let mut gaps = values.windows(2).map(|pair| pair[1] - pair[0]);
+            assert!(gaps.all(|gap| gap <= 2.0 / 16.0 + 1e-6));
+            assert!(wrapped_gap <= 2.0 / 16.0 + 1e-6);
+        }
+        // Neighboring pixels get different rotations
+        sampler.start_pixel_sample(5, 9, 0);
+        let first = sampler.get_1d();
+        sampler.start_pixel_sample(5, 10, 0);
+        assert_ne!(first, sampler.get_1d());
+    }
+
+    #[test]
+    fn blue_noise_mask_is_a_permutation_of_ranks_test() {
+        let mask = generate_blue_noise(16);
+        let ranks: Vec<usize> = mask.iter().map(|value| (value * 256.0) as usize).collect();
+        let mut sorted_ranks = ranks.clone();
+        sorted_ranks.sort_unstable();
+        assert_eq!(sorted_ranks, (0..256).collect::<Vec<usize>>());
+        // The first points spread out: no two of the first tenth are neighbors
+        let first: Vec<usize> = (0..256).filter(|&k| ranks[k] < 25).collect();
+        for &a in first.iter() {
+            for &b in first.iter() {
+                let dy = (a / 16).max(b / 16) - (a / 16).min(b / 16);
+                let dx = (a % 16).max(b % 16) - (a % 16).min(b % 16);
+                assert!(a == b || dx.min(16 - dx) + dy.min(16 - dy) > 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use super::math;
+use std::f32::consts::PI;
+
+// Direction in the local frame where the hemisphere is centered on +z
+pub fn uniform_sample_hemisphere(u: (f32, f32)) -> math::Vector {
+    let cos_theta = u.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    math::Vector::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+// Direction in the local frame where the hemisphere is centered on +z, with density cos(θ) / π
+pub fn cosine_sample_hemisphere(u: (f32, f32)) -> math::Vector {
+    let radius = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    let x = radius * phi.cos();
+    let y = radius * phi.sin();
+    math::Vector::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+// Point on the unit disk, using Shirley's concentric mapping to keep the strata well shaped
+pub fn concentric_sample_disk(u: (f32, f32)) -> (f32, f32) {
+    let x = 2.0 * u.0 - 1.0;
+    let y = 2.0 * u.1 - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+// Point on a regular polygon inscribed in the unit circle, with one vertex at `rotation` radians
+pub fn uniform_sample_polygon(u: (f32, f32), sides: u32, rotation: f32) -> (f32, f32) {
+    let sides_f = sides as f32;
+    let sector = ((u.0 * sides_f) as u32).min(sides - 1);
+    let u0 = u.0 * sides_f - sector as f32;
+    // Uniform point in the triangle made by the center and the two vertices of the sector
+    let root = u0.sqrt();
+    let (b1, b2) = (root * (1.0 - u.1), root * u.1);
+    let angle1 = rotation + 2.0 * PI * sector as f32 / sides_f;
+    let angle2 = rotation + 2.0 * PI * (sector + 1) as f32 / sides_f;
+    (
+        b1 * angle1.cos() + b2 * angle2.cos(),
+        b1 * angle1.sin() + b2 * angle2.sin(),
+    )
+}
+
+// Direction in the local frame inside a cone centered on +z, with uniform density over its solid angle
+pub fn uniform_sample_cone(u: (f32, f32), cos_theta_max: f32) -> math::Vector {
+    let cos_theta = (1.0 - u.0) + u.0 * cos_theta_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    math::Vector::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+/*
+Weight given to a sample taken with the first strategy, when combining it with a second one through
+multiple importance sampling. Each pdf is multiplied by the number of samples taken with it.
+*/
+pub fn power_heuristic(n_f: f32, f_pdf: f32, n_g: f32, g_pdf: f32) -> f32 {
+    let f = n_f * f_pdf;
+    let g = n_g * g_pdf;
+    if f == 0.0 && g == 0.0 {
+        return 0.0;
+    }
+    (f * f) / (f * f + g * g)
+}
+
+pub fn balance_heuristic(n_f: f32, f_pdf: f32, n_g: f32, g_pdf: f32) -> f32 {
+    let f = n_f * f_pdf;
+    let g = n_g * g_pdf;
+    if f == 0.0 && g == 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+// Piecewise-constant distribution used to pick an index in proportion to its weight
+pub struct Distribution1D {
+    weights: Vec<f32>,
+    cdf: Vec<f32>,
+    total: f32,
+}
+
+impl Distribution1D {
+    pub fn new(weights: &[f32]) -> Distribution1D {
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        let mut total = 0.0;
+        for weight in weights.iter() {
+            total += weight.max(0.0);
+            cdf.push(total);
+        }
+        Distribution1D {
+            weights: weights.to_vec(),
+            cdf,
+            total,
+        }
+    }
+
+    // Index picked by `u`, a uniform random number in [0, 1), with the probability of picking it
+    pub fn sample_discrete(&self, u: f32) -> Option<(usize, f32)> {
+        if self.weights.is_empty() {
+            return None;
+        }
+        if self.total <= 0.0 {
+            // Falls back to a uniform choice when every weight is zero
+            let index = ((u * self.weights.len() as f32) as usize).min(self.weights.len() - 1);
+            return Some((index, 1.0 / self.weights.len() as f32));
+        }
+        let target = u * self.total;
+        let index = self.cdf[1..]
+            .partition_point(|&value| value <= target)
+            .min(self.weights.len() - 1);
+        Some((index, self.get_pmf(index)))
+    }
+
+    pub fn get_pmf(&self, index: usize) -> f32 {
+        if self.total <= 0.0 {
+            return 1.0 / self.weights.len() as f32;
+        }
+        self.weights[index].max(0.0) / self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_sample_is_normalized_test() {
+        let direction = cosine_sample_hemisphere((0.3, 0.7));
+        assert!((direction.magnitude() - 1.0).abs() < 1e-6);
+        assert!(direction.z >= 0.0);
+    }
+
+    #[test]
+    fn disk_samples_stay_inside_the_unit_circle_test() {
+        for &u in [(0.0, 0.0), (0.99, 0.5), (0.3, 0.01), (0.5, 0.5)].iter() {
+            let (x, y) = concentric_sample_disk(u);
+            assert!(x * x + y * y <= 1.0 + 1e-6);
+            let (x, y) = uniform_sample_polygon(u, 6, 0.3);
+            assert!(x * x + y * y <= 1.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn discrete_sampling_follows_weights_test() {
+        let distribution = Distribution1D::new(&[1.0, 0.0, 3.0]);
+        assert_eq!(distribution.sample_discrete(0.1), Some((0, 0.25)));
+        assert_eq!(distribution.sample_discrete(0.5), Some((2, 0.75)));
+        assert_eq!(distribution.get_pmf(1), 0.0);
+    }
+
+    #[test]
+    fn heuristics_sum_to_one_test() {
+        let w1 = power_heuristic(1.0, 0.4, 1.0, 1.3);
+        let w2 = power_heuristic(1.0, 1.3, 1.0, 0.4);
+        assert!((w1 + w2 - 1.0).abs() < 1e-6);
+        let w1 = balance_heuristic(1.0, 0.4, 1.0, 1.3);
+        let w2 = balance_heuristic(1.0, 1.3, 1.0, 0.4);
+        assert!((w1 + w2 - 1.0).abs() < 1e-6);
+    }
+}
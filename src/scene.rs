@@ -1,78 +1,409 @@
-use super::{light, math, object, util};
+use super::{light, light_sampler, math, object, sampler, sampling, sky, util};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-const BACKGROUND_COLOR: util::Color = util::Color {
-    r: 0.0,
-    g: 0.0,
-    b: 0.0,
-};
+pub enum Background {
+    Color(util::Color),
+    Sky(sky::Sky),
+}
+
+impl Background {
+    pub fn get_radiance(&self, direction: &math::Vector) -> util::Color {
+        match self {
+            Background::Color(color) => *color,
+            Background::Sky(sky) => sky.get_radiance(direction),
+        }
+    }
+}
+
+// Surface hit by a camera ray
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub distance: f32,
+    pub position: math::Vector,
+    pub normal: math::Vector,
+    pub albedo: util::Color,
+    // Starting at 1, the index of the object in the scene and of its surface among the distinct ones
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/*
+Light arriving at the camera along a ray, split into the parts that compositing works on, along
+with the surface the ray hits. The scene only computes direct lighting, so there is no indirect part,
+which leaves the indirect AOVs black, and the color of the ray is the sum of the emitted light and of
+the direct diffuse and specular light.
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct RadianceSample {
+    // Light of the area lights and of the background seen along the ray
+    pub emitted: util::Color,
+    pub direct_diffuse: util::Color,
+    pub direct_specular: util::Color,
+    // Direct light the objects casting shadows keep from reaching the surface
+    pub shadow: util::Color,
+    pub hit: Option<Hit>,
+}
+
+impl RadianceSample {
+    pub fn new(emitted: util::Color) -> RadianceSample {
+        let black = util::Color::new(0.0, 0.0, 0.0);
+        RadianceSample {
+            emitted,
+            direct_diffuse: black,
+            direct_specular: black,
+            shadow: black,
+            hit: None,
+        }
+    }
+
+    pub fn get_color(&self) -> util::Color {
+        self.emitted + self.direct_diffuse + self.direct_specular
+    }
+
+    // Scales the light, as cameras do with their exposure, leaving the surface as it is
+    pub fn with_light_scale(mut self, scale: f32) -> RadianceSample {
+        self.emitted = self.emitted * scale;
+        self.direct_diffuse = self.direct_diffuse * scale;
+        self.direct_specular = self.direct_specular * scale;
+        self.shadow = self.shadow * scale;
+        self
+    }
+}
+
+// Light reaching a point from the lights, and the light that would reach it without shadows
+struct DirectLighting {
+    diffuse: util::Color,
+    specular: util::Color,
+    unoccluded: util::Color,
+}
 
 pub struct Scene {
-    lights: Vec<light::Light>,
-    objects: Vec<Box<object::Object>>,
+    lights: Vec<Box<dyn light::Light>>,
+    objects: Vec<Box<dyn object::Object>>,
+    background: Background,
+    light_sampler: light_sampler::LightSampler,
+    material_ids: Vec<u32>,
+    // Camera and shadow rays traced so far, to measure the speed of renders
+    ray_count: AtomicU64,
 }
 
 impl Scene {
-    pub fn new(lights: Vec<light::Light>, objects: Vec<Box<object::Object>>) -> Scene {
+    pub fn new(
+        lights: Vec<Box<dyn light::Light>>,
+        objects: Vec<Box<dyn object::Object>>,
+        background: Background,
+    ) -> Scene {
+        let light_sampler = light_sampler::LightSampler::new(&lights, light_sampler::Strategy::All);
+        let mut surfaces: Vec<object::Surface> = Vec::new();
+        let material_ids = objects
+            .iter()
+            .map(|object| {
+                let surface = object.get_surface();
+                match surfaces.iter().position(|other| other == surface) {
+                    Some(index) => index as u32 + 1,
+                    None => {
+                        surfaces.push(*surface);
+                        surfaces.len() as u32
+                    }
+                }
+            })
+            .collect();
         Scene {
             lights: lights,
             objects: objects,
+            background: background,
+            light_sampler: light_sampler,
+            material_ids: material_ids,
+            ray_count: AtomicU64::new(0),
         }
     }
 
-    pub fn compute_color(&self, ray: &util::Ray) -> util::Color {
-        let mut color = util::Color::new(0.0, 0.0, 0.0);
-        let mut shortest_distance = std::f32::MAX;
+    // Scenes with many lights should choose one light per shading point instead of sampling them all
+    pub fn set_light_sampling(&mut self, strategy: light_sampler::Strategy) {
+        self.light_sampler = light_sampler::LightSampler::new(&self.lights, strategy);
+    }
+
+    pub fn get_ray_count(&self) -> u64 {
+        self.ray_count.load(Ordering::Relaxed)
+    }
+
+    pub fn compute_color(
+        &self,
+        ray: &util::Ray,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> util::Color {
+        self.compute_radiance(ray, sampler).get_color()
+    }
+
+    pub fn compute_radiance(
+        &self,
+        ray: &util::Ray,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> RadianceSample {
+        self.ray_count.fetch_add(1, Ordering::Relaxed);
+        let mut shortest_distance = f32::MAX;
         let mut closest_intersection_point = math::Vector::new(0.0, 0.0, 0.0);
         let mut closest_object_index = 0;
         let mut is_ray_intersecting_an_object = false;
 
         for (i, object) in self.objects.iter().enumerate() {
-            let intersection_point = object.get_point_intersected_by(ray);
-            match intersection_point {
-                Some(point) => {
-                    is_ray_intersecting_an_object = true;
-                    let distance_to_intersection_point = (point - ray.origin).magnitude();
-                    if distance_to_intersection_point < shortest_distance {
-                        shortest_distance = distance_to_intersection_point;
-                        closest_intersection_point = point;
-                        closest_object_index = i;
-                    }
+            if let Some(point) = object.get_point_intersected_by(ray) {
+                is_ray_intersecting_an_object = true;
+                let distance_to_intersection_point = (point - ray.origin).magnitude();
+                if distance_to_intersection_point < shortest_distance {
+                    shortest_distance = distance_to_intersection_point;
+                    closest_intersection_point = point;
+                    closest_object_index = i;
+                }
+            }
+        }
+
+        // Area lights are visible to the camera when they are in front of every object
+        for light in self.lights.iter() {
+            if let Some((distance, radiance)) = light.get_radiance_along(ray) {
+                if distance < shortest_distance && distance < f32::MAX {
+                    return RadianceSample::new(radiance);
                 }
-                None => (),
             }
         }
 
-        if is_ray_intersecting_an_object {
-            for light in self.lights.iter() {
-                let shadow_ray = Scene::generate_shadow_ray(&light, &closest_intersection_point);
-                let mut is_shadow_ray_intersecting_object = false;
-                for object in self.objects.iter() {
-                    if object.is_intersected_by(&shadow_ray) {
-                        is_shadow_ray_intersecting_object = true;
+        if !is_ray_intersecting_an_object {
+            return RadianceSample::new(self.background.get_radiance(&ray.direction));
+        }
+        let object = &self.objects[closest_object_index];
+        let normal = object.get_normal_at(&closest_intersection_point, ray.time);
+        let mut radiance = RadianceSample::new(util::Color::new(0.0, 0.0, 0.0));
+        let mut unoccluded = util::Color::new(0.0, 0.0, 0.0);
+        let selected_lights = self
+            .light_sampler
+            .select(&closest_intersection_point, sampler.get_1d());
+        for (light_index, probability) in selected_lights {
+            let lighting = self.estimate_direct_lighting(
+                self.lights[light_index].as_ref(),
+                object.get_surface(),
+                &closest_intersection_point,
+                &normal,
+                ray,
+                sampler,
+            );
+            radiance.direct_diffuse += lighting.diffuse * (1.0 / probability);
+            radiance.direct_specular += lighting.specular * (1.0 / probability);
+            unoccluded += lighting.unoccluded * (1.0 / probability);
+        }
+        let lit = radiance.direct_diffuse + radiance.direct_specular;
+        radiance.shadow = util::Color::new(
+            unoccluded.r - lit.r,
+            unoccluded.g - lit.g,
+            unoccluded.b - lit.b,
+        );
+        radiance.hit = Some(Hit {
+            distance: shortest_distance,
+            position: closest_intersection_point,
+            normal,
+            albedo: object.get_surface().get_albedo(),
+            object_id: closest_object_index as u32 + 1,
+            material_id: self.material_ids[closest_object_index],
+        });
+        radiance
+    }
+
+    /*
+    Estimates the light arriving at the point from a single light. Delta lights can only be sampled,
+    so the BRDF is evaluated for the only direction they light the point from. The other lights combine a sample of the light with a sample of the
+    surface BRDF through multiple importance sampling, weighting each one with the power heuristic,
+    which keeps the noise low for both small bright lights and sharp highlights.
+    */
+    fn estimate_direct_lighting(
+        &self,
+        light: &dyn light::Light,
+        surface: &object::Surface,
+        point: &math::Vector,
+        normal: &math::Vector,
+        ray: &util::Ray,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> DirectLighting {
+        let black = util::Color::new(0.0, 0.0, 0.0);
+        let mut lighting = DirectLighting {
+            diffuse: black,
+            specular: black,
+            unoccluded: black,
+        };
+        let mut add = |(diffuse, specular): (util::Color, util::Color), transmittance| {
+            lighting.diffuse += diffuse * transmittance;
+            lighting.specular += specular * transmittance;
+            lighting.unoccluded += diffuse + specular;
+        };
+        let wo = -ray.direction.normalize();
+        if light.is_delta() {
+            if let Some(light_sample) = light.sample(point, sampler.get_2d()) {
+                let transmittance = self.get_transmittance(
+                    point,
+                    &light_sample.direction,
+                    light_sample.distance,
+                    ray.time,
+                );
+                let (diffuse, specular) =
+                    surface.evaluate_lobes(normal, &wo, &light_sample.direction);
+                add(
+                    (
+                        diffuse * light_sample.radiance,
+                        specular * light_sample.radiance,
+                    ),
+                    transmittance,
+                );
+            }
+            return lighting;
+        }
+
+        if let Some(light_sample) = light.sample(point, sampler.get_2d()) {
+            let (diffuse, specular) = surface.evaluate_lobes(normal, &wo, &light_sample.direction);
+            if light_sample.pdf > 0.0 && !(diffuse + specular).is_black() {
+                let transmittance = self.get_transmittance(
+                    point,
+                    &light_sample.direction,
+                    light_sample.distance,
+                    ray.time,
+                );
+                let bsdf_pdf = surface.get_pdf(normal, &wo, &light_sample.direction);
+                let weight = sampling::power_heuristic(1.0, light_sample.pdf, 1.0, bsdf_pdf);
+                let scale = light_sample.radiance * (weight / light_sample.pdf);
+                add((diffuse * scale, specular * scale), transmittance);
+            }
+        }
+
+        if let Some((wi, bsdf_pdf)) = surface.sample(normal, &wo, sampler.get_2d()) {
+            if let Some((distance, radiance)) =
+                light.get_radiance_along(&util::Ray::new(*point, wi).with_time(ray.time))
+            {
+                let transmittance = self.get_transmittance(point, &wi, distance, ray.time);
+                let light_pdf = light.get_pdf(point, &wi);
+                let weight = sampling::power_heuristic(1.0, bsdf_pdf, 1.0, light_pdf);
+                let scale = radiance * (weight / bsdf_pdf);
+                let (diffuse, specular) = surface.evaluate_lobes(normal, &wo, &wi);
+                add((diffuse * scale, specular * scale), transmittance);
+            }
+        }
+        lighting
+    }
+
+    /*
+    Fraction of the light that reaches the point through the objects in between, found by tracing a
+    shadow ray. Opaque objects block it entirely, while transmissive ones, like glass or partially
+    opaque materials, filter it by their transmittance, tinting the shadows they cast.
+    */
+    fn get_transmittance(
+        &self,
+        point: &math::Vector,
+        direction: &math::Vector,
+        distance: f32,
+        time: f32,
+    ) -> util::Color {
+        self.ray_count.fetch_add(1, Ordering::Relaxed);
+        let shadow_ray =
+            util::Ray::new(*point + (*direction * f32::EPSILON), *direction).with_time(time);
+        let mut transmittance = util::Color::new(1.0, 1.0, 1.0);
+        for object in self.objects.iter() {
+            if let Some(hit) = object.get_point_intersected_by(&shadow_ray) {
+                if (hit - *point).magnitude() < distance {
+                    transmittance = transmittance * object.get_surface().get_transmittance();
+                    if transmittance.is_black() {
                         break;
                     }
                 }
-                if !is_shadow_ray_intersecting_object {
-                    color += self.objects[closest_object_index].compute_color(
-                        &closest_intersection_point,
-                        &ray,
-                        &light,
-                    );
-                }
             }
-        } else {
-            color = BACKGROUND_COLOR;
         }
-        color.clamp();
-        return color;
+        transmittance
     }
+}
 
-    fn generate_shadow_ray(light: &light::Light, point: &math::Vector) -> util::Ray {
-        let shadow_ray_direction = (light.position - *point).normalize();
-        let shadow_ray = util::Ray::new(
-            *point + (shadow_ray_direction * std::f32::EPSILON),
-            shadow_ray_direction,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn shadow_through_transmissive_object_is_tinted_test() {
+        let glass = object::Surface::new(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 0.0),
+        )
+        .with_transmittance(math::Vector::new(0.5, 0.25, 1.0));
+        let objects: Vec<Box<dyn object::Object>> = vec![Box::new(object::Sphere::new(
+            math::Vector::new(0.0, 1.0, 0.0),
+            0.5,
+            glass,
+        ))];
+        let scene = Scene::new(
+            vec![],
+            objects,
+            Background::Color(util::Color::new(0.0, 0.0, 0.0)),
+        );
+        let point = math::Vector::new(0.0, 0.0, 0.0);
+        let up = math::Vector::new(0.0, 1.0, 0.0);
+        assert_eq!(
+            scene.get_transmittance(&point, &up, 3.0, 0.0),
+            util::Color::new(0.5, 0.25, 1.0)
         );
-        return shadow_ray;
+        assert_eq!(
+            scene.get_transmittance(&point, &up, 0.2, 0.0),
+            util::Color::new(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn small_sphere_light_matches_point_light_test() {
+        let surface = object::Surface::new(
+            math::Vector::new(0.8, 0.8, 0.8),
+            math::Vector::new(0.5, 0.5, 0.5),
+        );
+        let scene = Scene::new(
+            vec![],
+            vec![],
+            Background::Color(util::Color::new(0.0, 0.0, 0.0)),
+        );
+        let point = math::Vector::new(0.0, 0.0, 0.0);
+        let normal = math::Vector::new(0.0, 1.0, 0.0);
+        let ray = util::Ray::new(
+            math::Vector::new(-0.5, 1.0, 0.0),
+            math::Vector::new(0.5, -1.0, 0.0).normalize(),
+        );
+        let position = math::Vector::new(0.5, 2.0, 0.0);
+        let radius = 0.01;
+        // Seen from afar, a sphere of radiance L has an intensity of L * π * r² in every direction
+        let radiance = 1.0 / (std::f32::consts::PI * radius * radius);
+        let lights: [Box<dyn light::Light>; 2] = [
+            Box::new(light::PointLight::new(position, 1.0)),
+            Box::new(light::SphereLight::new(
+                position,
+                radius,
+                util::Color::new(radiance, radiance, radiance),
+            )),
+        ];
+        let mut sampler = sampler::RandomSampler::new(1);
+        let estimates: Vec<(f32, f32)> = lights
+            .iter()
+            .map(|light| {
+                let (mut diffuse, mut specular) = (0.0, 0.0);
+                for sample_index in 0..64 {
+                    sampler.start_pixel_sample(0, 0, sample_index);
+                    let lighting = scene.estimate_direct_lighting(
+                        light.as_ref(),
+                        &surface,
+                        &point,
+                        &normal,
+                        &ray,
+                        &mut sampler,
+                    );
+                    diffuse += lighting.diffuse.r / 64.0;
+                    specular += lighting.specular.r / 64.0;
+                }
+                (diffuse, specular)
+            })
+            .collect();
+        let (point_diffuse, point_specular) = estimates[0];
+        let (sphere_diffuse, sphere_specular) = estimates[1];
+        assert!(point_diffuse > 0.0 && point_specular > 0.0);
+        assert!((sphere_diffuse - point_diffuse).abs() < point_diffuse * 0.01);
+        assert!((sphere_specular - point_specular).abs() < point_specular * 0.01);
     }
 }
@@ -0,0 +1,226 @@
+use super::{light, math, sampling, util};
+use std::f32::consts::PI;
+
+// Scales the sky luminance, given by the model in kcd/m², to the range used by the renderer
+const SKY_LUMINANCE_SCALE: f32 = 0.05;
+const SUN_INTENSITY: f32 = 1.0;
+
+/*
+Procedural daylight sky following the analytic model from Preetham, Shirley and Smits,
+"A Practical Analytic Model for Daylight" (1999). The sun is placed with its elevation above the
+horizon and its azimuth, measured from the +z axis towards the +x axis, both in degrees. The
+turbidity describes the haziness of the atmosphere, going from 2 (clear) to around 10 (hazy).
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct Sky {
+    sun_direction: math::Vector,
+    sun_zenith_angle: f32,
+    turbidity: f32,
+    perez_luminance: [f32; 5],
+    perez_x: [f32; 5],
+    perez_y: [f32; 5],
+    zenith_luminance: f32,
+    zenith_x: f32,
+    zenith_y: f32,
+}
+
+impl Sky {
+    pub fn new(sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Sky {
+        let elevation = sun_elevation.to_radians();
+        let azimuth = sun_azimuth.to_radians();
+        let sun_direction = math::Vector::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos(),
+        );
+        // The model is not defined for a sun below the horizon
+        let theta = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0);
+        let t = turbidity;
+
+        let perez_luminance = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta2 = theta * theta;
+        let theta3 = theta2 * theta;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        Sky {
+            sun_direction,
+            sun_zenith_angle: theta,
+            turbidity,
+            perez_luminance,
+            perez_x,
+            perez_y,
+            zenith_luminance,
+            zenith_x,
+            zenith_y,
+        }
+    }
+
+    pub fn get_sun_direction(&self) -> math::Vector {
+        self.sun_direction
+    }
+
+    // Radiance of the sky seen along `direction`. Directions below the horizon are black.
+    pub fn get_radiance(&self, direction: &math::Vector) -> util::Color {
+        let direction = direction.normalize();
+        if direction.y <= 0.0 {
+            return util::Color::new(0.0, 0.0, 0.0);
+        }
+        let theta = direction.y.acos();
+        let gamma = math::Vector::dot_product(direction, self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let luminance = self.zenith_luminance * Sky::perez(&self.perez_luminance, theta, gamma)
+            / Sky::perez(&self.perez_luminance, 0.0, self.sun_zenith_angle);
+        let x = self.zenith_x * Sky::perez(&self.perez_x, theta, gamma)
+            / Sky::perez(&self.perez_x, 0.0, self.sun_zenith_angle);
+        let y = self.zenith_y * Sky::perez(&self.perez_y, theta, gamma)
+            / Sky::perez(&self.perez_y, 0.0, self.sun_zenith_angle);
+
+        Sky::xyy_to_rgb(x, y, luminance.max(0.0) * SKY_LUMINANCE_SCALE)
+    }
+
+    /*
+    Directional light matching the sun of this sky. Its color comes from the transmittance of the
+    atmosphere along the path of the sunlight, accounting for Rayleigh scattering and for aerosols,
+    whose amount grows with the turbidity.
+    */
+    pub fn get_sun_light(&self) -> light::DirectionalLight {
+        let elevation = 90.0 - self.sun_zenith_angle.to_degrees();
+        if self.sun_direction.y <= 0.0 {
+            return light::DirectionalLight::new(
+                -self.sun_direction,
+                util::Color::new(0.0, 0.0, 0.0),
+            );
+        }
+        // Kasten and Young's relative optical air mass
+        let air_mass =
+            1.0 / (self.sun_zenith_angle.cos() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let rayleigh_depth = [0.0451, 0.0970, 0.2362]; // At 680nm, 550nm and 440nm
+        let aerosol_depth = [
+            beta * 0.68f32.powf(-1.3),
+            beta * 0.55f32.powf(-1.3),
+            beta * 0.44f32.powf(-1.3),
+        ];
+        let transmittance = |i: usize| (-(rayleigh_depth[i] + aerosol_depth[i]) * air_mass).exp();
+
+        light::DirectionalLight::new(
+            -self.sun_direction,
+            util::Color::new(transmittance(0), transmittance(1), transmittance(2)) * SUN_INTENSITY,
+        )
+    }
+
+    fn perez(coefficients: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / theta.cos().max(0.01)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> util::Color {
+        let cie_x = x / y * luminance;
+        let cie_z = (1.0 - x - y) / y * luminance;
+        // Conversion from CIE XYZ to linear sRGB
+        util::Color::new(
+            (3.2406 * cie_x - 1.5372 * luminance - 0.4986 * cie_z).max(0.0),
+            (-0.9689 * cie_x + 1.8758 * luminance + 0.0415 * cie_z).max(0.0),
+            (0.0557 * cie_x - 0.2040 * luminance + 1.0570 * cie_z).max(0.0),
+        )
+    }
+}
+
+// The sky lights the scene as a dome, sampled uniformly over the upper hemisphere
+impl light::Light for Sky {
+    fn sample(&self, _point: &math::Vector, u: (f32, f32)) -> Option<light::LightSample> {
+        let up = math::Vector::new(0.0, 1.0, 0.0);
+        let direction = sampling::uniform_sample_hemisphere(u).to_world(up);
+        Some(light::LightSample {
+            direction,
+            distance: f32::MAX,
+            radiance: self.get_radiance(&direction),
+            pdf: 1.0 / (2.0 * PI),
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn get_pdf(&self, _point: &math::Vector, direction: &math::Vector) -> f32 {
+        if direction.y > 0.0 {
+            1.0 / (2.0 * PI)
+        } else {
+            0.0
+        }
+    }
+
+    fn get_radiance_along(&self, ray: &util::Ray) -> Option<(f32, util::Color)> {
+        if ray.direction.y > 0.0 {
+            Some((f32::MAX, self.get_radiance(&ray.direction)))
+        } else {
+            None
+        }
+    }
+
+    fn get_power(&self) -> f32 {
+        let zenith = math::Vector::new(0.0, 1.0, 0.0);
+        self.get_radiance(&zenith).get_luminance() * 2.0 * PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_direction_test() {
+        let sky = Sky::new(90.0, 0.0, 2.5);
+        let direction = sky.get_sun_direction();
+        assert!((direction.y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sky_is_brighter_around_the_sun_test() {
+        let sky = Sky::new(30.0, 0.0, 2.5);
+        let towards_sun = sky.get_radiance(&math::Vector::new(0.0, 0.6, 1.0));
+        let away_from_sun = sky.get_radiance(&math::Vector::new(0.0, 0.6, -1.0));
+        assert!(
+            towards_sun.r + towards_sun.g + towards_sun.b
+                > away_from_sun.r + away_from_sun.g + away_from_sun.b
+        );
+    }
+
+    #[test]
+    fn setting_sun_is_reddish_test() {
+        let color = Sky::new(5.0, 0.0, 3.0).get_sun_light().intensity;
+        assert!(color.r > color.g && color.g > color.b);
+    }
+}
@@ -0,0 +1,143 @@
+use super::{camera, image, util};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StereoLayout {
+    // Left eye on the left half of the image, right eye on the right half
+    SideBySide,
+    // Left eye on the top half of the image, right eye on the bottom half
+    TopBottom,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StereoMode {
+    // Both eyes look straight ahead, converging at infinity
+    Parallel,
+    // Both eyes look straight ahead, with their lenses shifted to converge at the given distance
+    OffAxis(f32),
+}
+
+/*
+Pair of perspective cameras rendered together into a single image, holding both views according to
+the layout. Each eye is moved by half of the interocular distance from the original camera.
+*/
+pub struct StereoCamera {
+    left: camera::PerspectiveCamera,
+    right: camera::PerspectiveCamera,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(
+        camera: camera::PerspectiveCamera,
+        interocular_distance: f32,
+        mode: StereoMode,
+        layout: StereoLayout,
+    ) -> StereoCamera {
+        let convergence_distance = match mode {
+            StereoMode::Parallel => None,
+            StereoMode::OffAxis(distance) => Some(distance),
+        };
+        StereoCamera {
+            left: camera.get_eye(-interocular_distance / 2.0, convergence_distance),
+            right: camera.get_eye(interocular_distance / 2.0, convergence_distance),
+            layout,
+        }
+    }
+
+    // Eye seeing the point (i, j) of the image, with the coordinates of the point in its own image
+    fn get_eye_at(&self, i: f32, j: f32) -> (&camera::PerspectiveCamera, f32, f32) {
+        let resolution = camera::Camera::get_resolution(&self.left);
+        match self.layout {
+            StereoLayout::SideBySide if j + 0.5 >= resolution.width => {
+                (&self.right, i, j - resolution.width)
+            }
+            StereoLayout::TopBottom if i + 0.5 >= resolution.height => {
+                (&self.right, i - resolution.height, j)
+            }
+            _ => (&self.left, i, j),
+        }
+    }
+
+    // Separates an image rendered with this camera into the images of the left and right eyes
+    pub fn split(&self, image: &image::Image) -> (image::Image, image::Image) {
+        let resolution = camera::Camera::get_resolution(&self.left);
+        let height = resolution.height as usize;
+        let width = resolution.width as usize;
+        let (row_offset, column_offset) = match self.layout {
+            StereoLayout::SideBySide => (0, width),
+            StereoLayout::TopBottom => (height, 0),
+        };
+        let mut left = image::Image::new(height, width);
+        let mut right = image::Image::new(height, width);
+        for i in 0..height {
+            for j in 0..width {
+                left.set_pixel(i, j, image.get_pixel(i, j));
+                right.set_pixel(i, j, image.get_pixel(i + row_offset, j + column_offset));
+            }
+        }
+        (left, right)
+    }
+}
+
+impl camera::Camera for StereoCamera {
+    fn generate_ray(&self, i: f32, j: f32, lens_sample: (f32, f32)) -> Option<util::Ray> {
+        let (eye, i, j) = self.get_eye_at(i, j);
+        eye.generate_ray(i, j, lens_sample)
+    }
+
+    fn get_resolution(&self) -> camera::Dimensions {
+        let resolution = self.left.get_resolution();
+        match self.layout {
+            StereoLayout::SideBySide => {
+                camera::Dimensions::new(resolution.height, resolution.width * 2.0)
+            }
+            StereoLayout::TopBottom => {
+                camera::Dimensions::new(resolution.height * 2.0, resolution.width)
+            }
+        }
+    }
+
+    fn get_shutter_interval(&self) -> (f32, f32) {
+        self.left.get_shutter_interval()
+    }
+
+    fn get_exposure_scale(&self) -> f32 {
+        self.left.get_exposure_scale()
+    }
+
+    fn get_vignetting(&self, i: f32, j: f32) -> f32 {
+        let (eye, i, j) = self.get_eye_at(i, j);
+        eye.get_vignetting(i, j)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::math;
+
+    #[test]
+    fn off_axis_eyes_converge_at_the_convergence_distance_test() {
+        let camera = camera::PerspectiveCamera::look_at(
+            math::Vector::new(0.0, 0.0, 0.0),
+            math::Vector::new(0.0, 0.0, 1.0),
+            math::Vector::new(0.0, 1.0, 0.0),
+            camera::FieldOfView::Horizontal(60.0),
+            camera::Dimensions::new(10.0, 10.0),
+        );
+        let stereo = StereoCamera::new(
+            camera,
+            0.065,
+            StereoMode::OffAxis(2.0),
+            StereoLayout::SideBySide,
+        );
+        assert_eq!(stereo.get_resolution(), camera::Dimensions::new(10.0, 20.0));
+        let left = stereo.generate_ray(4.5, 4.5, (0.5, 0.5)).unwrap();
+        let right = stereo.generate_ray(4.5, 14.5, (0.5, 0.5)).unwrap();
+        let at_convergence =
+            |ray: &util::Ray| ray.origin + ray.direction * ((2.0 - ray.origin.z) / ray.direction.z);
+        assert!((left.origin - right.origin).magnitude() > 0.06);
+        assert!((at_convergence(&left) - at_convergence(&right)).magnitude() < 1e-5);
+    }
+}
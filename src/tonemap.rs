@@ -0,0 +1,164 @@
+use super::{image, util};
+
+// Curve compressing the unbounded radiance of the render into [0, 1]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMapper {
+    // Values above 1.0 are clipped
+    Clamp,
+    Reinhard,
+    // Reinhard curve reaching 1.0 at the given white point instead of at infinity
+    ExtendedReinhard(f32),
+    // Narkowicz's fit of the ACES filmic reference rendering transform
+    Aces,
+    // Hable's filmic curve from Uncharted 2
+    Hable,
+}
+
+impl ToneMapper {
+    fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMapper::Clamp => x,
+            ToneMapper::Reinhard => x / (1.0 + x),
+            ToneMapper::ExtendedReinhard(white) => x * (1.0 + x / (white * white)) / (1.0 + x),
+            ToneMapper::Aces => {
+                // The fit was made for values already scaled by 0.6
+                let x = x * 0.6;
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }
+            ToneMapper::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE_POINT: f32 = 11.2;
+                hable_curve(x * EXPOSURE_BIAS) / hable_curve(WHITE_POINT)
+            }
+        }
+        .min(1.0)
+    }
+}
+
+fn hable_curve(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+// Opto-electronic transfer function encoding linear values for a display
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TransferFunction {
+    Linear,
+    Srgb,
+    Rec709,
+}
+
+impl TransferFunction {
+    fn apply(self, x: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => x,
+            TransferFunction::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            TransferFunction::Rec709 => {
+                if x < 0.018 {
+                    4.5 * x
+                } else {
+                    1.099 * x.powf(0.45) - 0.099
+                }
+            }
+        }
+    }
+}
+
+/*
+Turns the linear radiance of a render into values ready to be shown or stored in an 8 or 16-bit file:
+the radiance is scaled by the exposure, compressed by the tone mapper and encoded by the transfer
+function. Tone mapping works on each channel separately, which desaturates the brightest colors
+towards white as film does.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DisplayTransform {
+    exposure: f32, // In stops
+    tone_mapper: ToneMapper,
+    transfer_function: TransferFunction,
+}
+
+impl DisplayTransform {
+    pub fn new(tone_mapper: ToneMapper, transfer_function: TransferFunction) -> DisplayTransform {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_mapper,
+            transfer_function,
+        }
+    }
+
+    pub fn with_exposure(mut self, stops: f32) -> DisplayTransform {
+        self.exposure = stops;
+        self
+    }
+
+    pub fn apply_to_color(&self, color: util::Color) -> util::Color {
+        let scale = 2_f32.powf(self.exposure);
+        let transform = |x: f32| {
+            self.transfer_function
+                .apply(self.tone_mapper.apply(x * scale))
+        };
+        util::Color::new(transform(color.r), transform(color.g), transform(color.b))
+    }
+
+    // Copy of the image with every pixel transformed, keeping its alpha as it is
+    pub fn apply(&self, image: &image::Image) -> image::Image {
+        let mut transformed = image.clone();
+        for i in 0..image.get_height() {
+            for j in 0..image.get_width() {
+                transformed.set_pixel(i, j, self.apply_to_color(image.get_pixel(i, j)));
+            }
+        }
+        transformed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_functions_test() {
+        assert!((TransferFunction::Srgb.apply(0.5) - 0.735_357).abs() < 1e-5);
+        assert!((TransferFunction::Srgb.apply(0.002) - 0.025_84).abs() < 1e-6);
+        assert!((TransferFunction::Rec709.apply(1.0) - 1.0).abs() < 1e-6);
+        assert_eq!(TransferFunction::Linear.apply(0.3), 0.3);
+    }
+
+    #[test]
+    fn tone_mappers_stay_in_display_range_test() {
+        assert_eq!(ToneMapper::Reinhard.apply(1.0), 0.5);
+        assert!((ToneMapper::ExtendedReinhard(4.0).apply(4.0) - 1.0).abs() < 1e-6);
+        assert!((ToneMapper::Hable.apply(5.6) - 1.0).abs() < 1e-6);
+        for mapper in [
+            ToneMapper::Clamp,
+            ToneMapper::Reinhard,
+            ToneMapper::ExtendedReinhard(4.0),
+            ToneMapper::Aces,
+            ToneMapper::Hable,
+        ]
+        .iter()
+        {
+            let mut previous = 0.0;
+            for k in 0..100 {
+                let value = mapper.apply(k as f32 * 0.2);
+                assert!(value >= previous && value <= 1.0);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_scales_before_tone_mapping_test() {
+        let transform = DisplayTransform::new(ToneMapper::Reinhard, TransferFunction::Linear)
+            .with_exposure(1.0);
+        let color = transform.apply_to_color(util::Color::new(0.5, 0.0, 3.0));
+        assert_eq!(color, util::Color::new(0.5, 0.0, 6.0 / 7.0));
+    }
+}
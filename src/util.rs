@@ -1,9 +1,10 @@
 use super::math;
-use std::ops::{Add, AddAssign, Div};
+use std::ops::{Add, AddAssign, Div, Mul};
 
 pub struct Ray {
     pub origin: math::Vector,
     pub direction: math::Vector,
+    pub time: f32, // Instant at which the ray sees the scene, within the shutter interval
 }
 
 impl Ray {
@@ -11,10 +12,17 @@ impl Ray {
         Ray {
             origin: origin,
             direction: direction,
+            time: 0.0,
         }
     }
+
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
+    }
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Color {
     pub r: f32, // Should have values between [0.0, 1.0]
     pub g: f32,
@@ -26,6 +34,14 @@ impl Color {
         Color { r: r, g: g, b: b }
     }
 
+    pub fn get_luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r == 0.0 && self.g == 0.0 && self.b == 0.0
+    }
+
     pub fn clamp(&mut self) {
         if self.r > 1.0 {
             self.r = 1.0;
@@ -61,6 +77,40 @@ impl AddAssign for Color {
     }
 }
 
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, other: Color) -> Color {
+        Color {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+        }
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, scalar: f32) -> Color {
+        Color {
+            r: self.r * scalar,
+            g: self.g * scalar,
+            b: self.b * scalar,
+        }
+    }
+}
+
+impl From<math::Vector> for Color {
+    fn from(v: math::Vector) -> Color {
+        Color {
+            r: v.x,
+            g: v.y,
+            b: v.z,
+        }
+    }
+}
+
 impl Div<u32> for Color {
     type Output = Color;
 
@@ -77,4 +127,10 @@ impl Div<u32> for Color {
 mod tests {
     use super::*;
 
+    #[test]
+    fn multiplication_of_colors_test() {
+        let c1 = Color::new(1.0, 0.5, 0.25);
+        let c2 = Color::new(0.5, 0.5, 2.0);
+        assert_eq!(c1 * c2, Color::new(0.5, 0.25, 0.5));
+    }
 }